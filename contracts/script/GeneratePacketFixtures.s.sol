@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: UNLICENSE
+pragma solidity ^0.8.20;
+
+import "forge-std/Script.sol";
+import "@layerzerolabs/lz-evm-protocol-v2/contracts/interfaces/ISendLib.sol";
+import "@layerzerolabs/lz-evm-protocol-v2/contracts/libs/GUID.sol";
+import "@layerzerolabs/lz-evm-protocol-v2/contracts/messagelib/libs/PacketV1Codec.sol";
+
+/**
+ * @title GeneratePacketFixtures
+ * @dev Regenerates `fixtures/packet_v1.json` with LayerZero's PacketV1Codec, so the off-chain
+ *      packet codec tests catch any drift from the protocol encoding.
+ *      Run with `forge script contracts/script/GeneratePacketFixtures.s.sol`.
+ */
+contract GeneratePacketFixtures is Script {
+    string internal constant ROOT = "fixtures";
+
+    function run() external {
+        bytes memory longMessage = new bytes(300);
+        for (uint256 i = 0; i < longMessage.length; i++) {
+            longMessage[i] = bytes1(uint8(i % 256));
+        }
+
+        _serialize(
+            "case0",
+            1,
+            30101,
+            0x1111111111111111111111111111111111111111,
+            30110,
+            bytes32(uint256(uint160(0x2222222222222222222222222222222222222222))),
+            bytes("hello layerzero")
+        );
+        _serialize(
+            "case1",
+            type(uint64).max,
+            type(uint32).max,
+            address(type(uint160).max),
+            40161,
+            0x5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f,
+            ""
+        );
+        string memory json = _serialize(
+            "case2",
+            42,
+            40161,
+            0xa0Cb889707d426A7A386870A03bc70d1b0697598,
+            40231,
+            bytes32(uint256(uint160(0xc7183455a4C133Ae270771860664b6B7ec320bB1))),
+            longMessage
+        );
+
+        vm.writeJson(json, "fixtures/packet_v1.json");
+    }
+
+    function _serialize(
+        string memory key,
+        uint64 nonce,
+        uint32 srcEid,
+        address sender,
+        uint32 dstEid,
+        bytes32 receiver,
+        bytes memory message
+    ) internal returns (string memory) {
+        bytes32 guid = GUID.generate(nonce, srcEid, sender, dstEid, receiver);
+        Packet memory packet = Packet(nonce, srcEid, sender, dstEid, receiver, guid, message);
+        bytes memory header = PacketV1Codec.encodePacketHeader(packet);
+        bytes32 payloadHash = keccak256(PacketV1Codec.encodePayload(packet));
+
+        vm.serializeUint(key, "nonce", nonce);
+        vm.serializeUint(key, "src_eid", srcEid);
+        vm.serializeAddress(key, "sender", sender);
+        vm.serializeUint(key, "dst_eid", dstEid);
+        vm.serializeBytes32(key, "receiver", receiver);
+        vm.serializeBytes32(key, "guid", guid);
+        vm.serializeBytes(key, "message", message);
+        vm.serializeBytes(key, "encoded", PacketV1Codec.encode(packet));
+        vm.serializeBytes(key, "header", header);
+        vm.serializeBytes32(key, "payload_hash", payloadHash);
+        string memory fixture =
+            vm.serializeBytes32(key, "message_id", keccak256(abi.encodePacked(header, payloadHash)));
+
+        return vm.serializeString(ROOT, key, fixture);
+    }
+}
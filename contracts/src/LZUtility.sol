@@ -3,6 +3,7 @@ pragma solidity >=0.8.13;
 
 import "@layerzerolabs/lz-evm-messagelib-v2/contracts/uln/dvn/DVN.sol";
 import "@layerzerolabs/lz-evm-messagelib-v2/contracts/uln/uln302/SendUln302.sol";
+import "@layerzerolabs/lz-evm-messagelib-v2/contracts/uln/uln302/ReceiveUln302.sol";
 import "@layerzerolabs/lz-evm-protocol-v2/contracts/interfaces/ISendLib.sol";
 import "@layerzerolabs/lz-evm-protocol-v2/contracts/interfaces/ILayerZeroEndpointV2.sol";
 
@@ -0,0 +1,658 @@
+//! Recovery of `assignJob` parameters from source chain transactions, and cross-checking of
+//! them against the packet they refer to
+
+use crate::chains::{Chain, ChainConfig, ConfirmationPolicy};
+use crate::codec::{self, PacketHeader};
+use crate::ILayerZeroDVN::{assignJobCall, AssignJobParam};
+use crate::ILayerZeroEndpointV2::PacketSent;
+use crate::ISendLib::Packet;
+use crate::LayerZeroDVNInstance::JobAssigned;
+use alloy_primitives::{Address, B256};
+use alloy_provider::ext::DebugApi;
+use alloy_provider::Provider;
+use alloy_rpc_types_eth::Log;
+use alloy_rpc_types_trace::geth::{
+    CallFrame, GethDebugBuiltInTracerType, GethDebugTracerType, GethDebugTracingOptions, GethTrace,
+};
+use alloy_sol_types::SolCall;
+use gadget_sdk::Error;
+use std::fmt;
+
+/// Where the parameters of an assignment were recovered from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignmentSource {
+    /// Our DVN's `JobAssigned` event paired with the `PacketSent` event of the same transaction
+    Events,
+    /// The `assignJob` call to our DVN in the transaction's call trace
+    Trace,
+    /// The transaction's own input, for transactions calling our DVN directly
+    TransactionInput,
+}
+
+/// Recover the `assignJob` parameters our DVN received in `tx_hash` on `source` for the packet
+/// `message_id`, or `None` if it was not assigned to us there. `logs` are the transaction's logs.
+///
+/// `assignJob` is called by the send ULN from within `endpoint.send`, so its calldata is not the
+/// transaction's input. The parameters are recovered, in order, from:
+/// 1. the `JobAssigned` event our DVN emitted, paired with the `PacketSent` event carrying the
+///    packet header, which works against any node;
+/// 2. the call tree returned by `debug_traceTransaction`, for nodes that support tracing;
+/// 3. the transaction input, for transactions that call our DVN directly.
+pub async fn recover_assignment(
+    source: &Chain,
+    tx_hash: B256,
+    logs: &[Log],
+    message_id: B256,
+) -> Result<Option<(AssignJobParam, AssignmentSource)>, Error> {
+    if let Some(params) = assignment_for_message(logs, message_id, &source.config) {
+        return Ok(Some((params, AssignmentSource::Events)));
+    }
+
+    let dvns = source.config.dvns().copied().collect::<Vec<_>>();
+    match assignment_from_trace(source, tx_hash, &dvns, message_id).await {
+        Ok(Some(params)) => return Ok(Some((params, AssignmentSource::Trace))),
+        Ok(None) => {}
+        Err(e) => tracing::debug!("Could not trace transaction {}: {}", tx_hash, e),
+    }
+
+    let tx = source
+        .provider
+        .get_transaction_by_hash(tx_hash)
+        .await?
+        .ok_or_else(|| Error::Client(format!("Transaction {} not found", tx_hash)))?;
+    Ok(assignJobCall::abi_decode(&tx.input, true)
+        .ok()
+        .map(|call| call._param)
+        .filter(|params| assigns(params, message_id))
+        .map(|params| (params, AssignmentSource::TransactionInput)))
+}
+
+/// Whether `params` assign the packet `message_id`
+fn assigns(params: &AssignJobParam, message_id: B256) -> bool {
+    codec::message_id(&params.packetHeader, params.payloadHash) == message_id
+}
+
+/// Rebuild the `AssignJobParam` behind `job`, emitted by `job_log`, from the `PacketSent` event
+/// of the same send among `logs`, the logs of the transaction that emitted it
+pub(crate) fn assignment_for_job(
+    job: &JobAssigned,
+    job_log: &Log,
+    logs: &[Log],
+    endpoint: Address,
+) -> Option<AssignJobParam> {
+    match_job_assigned(job, &packet_of_send(logs, job_log, endpoint)?)
+}
+
+/// Rebuild the `AssignJobParam` for `message_id` from the `JobAssigned` events our DVNs on
+/// `source` emitted among `logs`, the logs of a single transaction
+pub(crate) fn assignment_for_message(
+    logs: &[Log],
+    message_id: B256,
+    source: &ChainConfig,
+) -> Option<AssignJobParam> {
+    logs.iter()
+        .filter(|log| source.dvns().any(|dvn| *dvn == log.address()))
+        .filter_map(|log| {
+            let job = log.log_decode::<JobAssigned>().ok()?;
+            assignment_for_job(&job.inner.data, log, logs, source.endpoint)
+        })
+        .find(|params| assigns(params, message_id))
+}
+
+/// Logs emitted by `tx_hash` on `source`, empty if it was not mined yet
+pub(crate) async fn receipt_logs(source: &Chain, tx_hash: B256) -> Result<Vec<Log>, Error> {
+    let receipt = source
+        .provider
+        .get_transaction_receipt(tx_hash)
+        .await
+        .map_err(|e| Error::Client(format!("Failed to get receipt for {}: {}", tx_hash, e)))?;
+
+    Ok(receipt
+        .map(|receipt| receipt.inner.logs().to_vec())
+        .unwrap_or_default())
+}
+
+/// Packets sent through `endpoint` in `logs`. Malformed packets, or ones whose GUID does not
+/// match, are skipped so they do not prevent recovering the assignments of the others.
+pub(crate) fn sent_packets(logs: &[Log], endpoint: Address) -> Vec<Packet> {
+    logs.iter()
+        .filter_map(|log| match sent_packet(log, endpoint)? {
+            Ok(packet) => Some(packet),
+            Err(e) => {
+                tracing::warn!(
+                    "Skipping invalid packet in transaction {:?}: {}",
+                    log.transaction_hash,
+                    e
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// The packet of the `endpoint.send` call that emitted `log`, one of `logs`. The send library's
+/// events, such as `DVNFeePaid` and our DVNs' `JobAssigned`, precede the endpoint's `PacketSent`
+/// event, so it is the first packet sent after `log`. `None` if that packet is invalid.
+pub(crate) fn packet_of_send(logs: &[Log], log: &Log, endpoint: Address) -> Option<Packet> {
+    let position = logs
+        .iter()
+        .position(|other| log.log_index.is_some() && other.log_index == log.log_index)?;
+
+    logs[position + 1..]
+        .iter()
+        .find_map(|log| sent_packet(log, endpoint))?
+        .ok()
+}
+
+/// The packet in `log` if it is a `PacketSent` event of `endpoint`, failing if it is malformed
+/// or its GUID does not match
+fn sent_packet(log: &Log, endpoint: Address) -> Option<Result<Packet, Error>> {
+    if log.address() != endpoint {
+        return None;
+    }
+    let packet_sent = log.log_decode::<PacketSent>().ok()?;
+
+    Some(
+        codec::decode_packet(&packet_sent.inner.data.encodedPayload)
+            .and_then(|packet| codec::validate_guid(&packet).map(|()| packet)),
+    )
+}
+
+/// Build the `AssignJobParam` behind `job` from `packet`, the packet of the send that emitted it
+fn match_job_assigned(job: &JobAssigned, packet: &Packet) -> Option<AssignJobParam> {
+    if packet.dstEid != job.dstEid || codec::payload_hash(packet) != job.payloadHash {
+        return None;
+    }
+
+    Some(AssignJobParam {
+        dstEid: job.dstEid,
+        packetHeader: PacketHeader::from(packet).encode(),
+        payloadHash: job.payloadHash,
+        confirmations: job.confirmations,
+        sender: job.sender,
+    })
+}
+
+async fn assignment_from_trace(
+    source: &Chain,
+    tx_hash: B256,
+    dvns: &[Address],
+    message_id: B256,
+) -> Result<Option<AssignJobParam>, Error> {
+    let options = GethDebugTracingOptions::default().with_tracer(
+        GethDebugTracerType::BuiltInTracer(GethDebugBuiltInTracerType::CallTracer),
+    );
+    let trace = source
+        .provider
+        .debug_trace_transaction(tx_hash, options)
+        .await
+        .map_err(|e| Error::Client(format!("Failed to trace {}: {}", tx_hash, e)))?;
+
+    let GethTrace::CallTracer(frame) = trace else {
+        return Ok(None);
+    };
+    Ok(find_assign_job(&frame, dvns, message_id))
+}
+
+/// Find the successful `assignJob` call to one of `dvns` for the packet `message_id` in a call
+/// tree
+fn find_assign_job(
+    frame: &CallFrame,
+    dvns: &[Address],
+    message_id: B256,
+) -> Option<AssignJobParam> {
+    if frame.error.is_some() {
+        return None;
+    }
+    if frame.to.is_some_and(|to| dvns.contains(&to))
+        && frame.input.starts_with(&assignJobCall::SELECTOR)
+    {
+        if let Ok(call) = assignJobCall::abi_decode(&frame.input, true) {
+            if assigns(&call._param, message_id) {
+                return Some(call._param);
+            }
+        }
+    }
+
+    frame
+        .calls
+        .iter()
+        .find_map(|call| find_assign_job(call, dvns, message_id))
+}
+
+/// A field of an assignment that disagrees with the packet it refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMismatch {
+    pub field: &'static str,
+    /// Value carried by the assignment
+    pub assigned: String,
+    /// Value expected from the packet, the `PacketSent` log or the operator's configuration
+    pub expected: String,
+}
+
+/// Every field of an assignment that disagrees with the packet it refers to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MismatchReport {
+    pub mismatches: Vec<FieldMismatch>,
+}
+
+impl MismatchReport {
+    pub fn is_empty(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    fn check<T: PartialEq + fmt::Display>(
+        &mut self,
+        field: &'static str,
+        assigned: T,
+        expected: T,
+    ) {
+        if assigned != expected {
+            self.push(field, assigned, expected);
+        }
+    }
+
+    fn push(
+        &mut self,
+        field: &'static str,
+        assigned: impl fmt::Display,
+        expected: impl fmt::Display,
+    ) {
+        self.mismatches.push(FieldMismatch {
+            field,
+            assigned: assigned.to_string(),
+            expected: expected.to_string(),
+        });
+    }
+}
+
+impl fmt::Display for MismatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, mismatch) in self.mismatches.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(
+                f,
+                "{}: assigned {}, expected {}",
+                mismatch.field, mismatch.assigned, mismatch.expected
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare every field of `params` against sources it is not derived from: the `PacketSent` logs
+/// of the transaction that made the assignment, the source chain's EID and confirmation bounds,
+/// and the destination chains we are configured for.
+pub fn check_assignment(
+    params: &AssignJobParam,
+    sent: &[Packet],
+    source_eid: u32,
+    confirmations: &ConfirmationPolicy,
+    destinations: &[u32],
+) -> MismatchReport {
+    let mut report = MismatchReport::default();
+
+    let header = match PacketHeader::decode(&params.packetHeader) {
+        Ok(header) => header,
+        Err(e) => {
+            report.push("packetHeader", params.packetHeader.clone(), e);
+            return report;
+        }
+    };
+
+    // The assignment must agree with the header it carries and with our configuration
+    report.check("srcEid", header.src_eid, source_eid);
+    report.check("dstEid", params.dstEid, header.dst_eid);
+    if !destinations.contains(&params.dstEid) {
+        report.push("dstEid", params.dstEid, "a configured destination chain");
+    }
+    report.check("sender", params.sender, header.sender_address());
+    if params.confirmations < confirmations.min_confirmations {
+        report.push(
+            "confirmations",
+            params.confirmations,
+            format!("at least {}", confirmations.min_confirmations),
+        );
+    }
+    if let Some(max_confirmations) = confirmations.max_confirmations {
+        if params.confirmations > max_confirmations {
+            report.push(
+                "confirmations",
+                params.confirmations,
+                format!("at most {}", max_confirmations),
+            );
+        }
+    }
+
+    // And with the packet its transaction emitted in PacketSent. Pick the packet it refers to,
+    // or failing that the one with its nonce, so every differing field is reported
+    let message_id = codec::message_id(&params.packetHeader, params.payloadHash);
+    let packet = sent
+        .iter()
+        .find(|packet| codec::packet_message_id(packet) == message_id)
+        .or_else(|| sent.iter().find(|packet| packet.nonce == header.nonce))
+        .or(match sent {
+            [packet] => Some(packet),
+            _ => None,
+        });
+    let Some(packet) = packet else {
+        report.push(
+            "packet",
+            message_id,
+            "a PacketSent log in the same transaction",
+        );
+        return report;
+    };
+
+    let expected = PacketHeader::from(packet);
+    report.check("nonce", header.nonce, expected.nonce);
+    report.check("packet srcEid", header.src_eid, expected.src_eid);
+    report.check("packet sender", header.sender, expected.sender);
+    report.check("packet dstEid", header.dst_eid, expected.dst_eid);
+    report.check("receiver", header.receiver, expected.receiver);
+    report.check(
+        "payloadHash",
+        params.payloadHash,
+        codec::payload_hash(packet),
+    );
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_packets;
+    use alloy_primitives::Bytes;
+    use alloy_sol_types::SolEvent;
+
+    const ENDPOINT: Address = Address::repeat_byte(0xee);
+
+    fn packet() -> Packet {
+        test_packets::packet(7, 30101)
+    }
+
+    fn log(address: Address, data: alloy_primitives::LogData, log_index: u64) -> Log {
+        Log {
+            inner: alloy_primitives::Log { address, data },
+            log_index: Some(log_index),
+            ..Default::default()
+        }
+    }
+
+    fn packet_sent(encoded_payload: Bytes, log_index: u64) -> Log {
+        let data = PacketSent {
+            encodedPayload: encoded_payload,
+            options: Bytes::new(),
+            sendLibrary: Address::ZERO,
+        }
+        .encode_log_data();
+        log(ENDPOINT, data, log_index)
+    }
+
+    fn job_assigned(params: &AssignJobParam) -> JobAssigned {
+        JobAssigned {
+            dstEid: params.dstEid,
+            payloadHash: params.payloadHash,
+            confirmations: params.confirmations,
+            sender: params.sender,
+        }
+    }
+
+    fn params(packet: &Packet) -> AssignJobParam {
+        AssignJobParam {
+            dstEid: packet.dstEid,
+            packetHeader: PacketHeader::from(packet).encode(),
+            payloadHash: codec::payload_hash(packet),
+            confirmations: 15,
+            sender: packet.sender,
+        }
+    }
+
+    fn fields(report: &MismatchReport) -> Vec<&'static str> {
+        report.mismatches.iter().map(|m| m.field).collect()
+    }
+
+    fn check(params: &AssignJobParam, sent: &[Packet]) -> MismatchReport {
+        let confirmations = ConfirmationPolicy {
+            min_confirmations: 10,
+            max_confirmations: Some(100),
+            ..Default::default()
+        };
+        check_assignment(params, sent, 30101, &confirmations, &[30110])
+    }
+
+    #[test]
+    fn test_matching_assignment() {
+        let packet = packet();
+        let report = check(&params(&packet), &[packet]);
+        assert!(report.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn test_reports_every_mismatch() {
+        let packet = packet();
+        let mut params = params(&packet);
+        params.dstEid = 1;
+        params.sender = Address::repeat_byte(0x33);
+        params.confirmations = 1;
+        params.payloadHash = B256::repeat_byte(0x44);
+
+        let report = check_assignment(
+            &params,
+            &[packet],
+            30102,
+            &ConfirmationPolicy {
+                min_confirmations: 10,
+                ..Default::default()
+            },
+            &[30110],
+        );
+        assert_eq!(
+            fields(&report),
+            [
+                "srcEid",
+                "dstEid",
+                "dstEid",
+                "sender",
+                "confirmations",
+                "payloadHash"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_unconfigured_destination() {
+        let mut packet = packet();
+        packet.dstEid = 30184;
+        let report = check(&params(&packet), &[packet]);
+        assert_eq!(fields(&report), ["dstEid"]);
+    }
+
+    #[test]
+    fn test_reports_confirmations_out_of_bounds() {
+        let packet = packet();
+        let mut params = params(&packet);
+        params.confirmations = 101;
+        assert_eq!(fields(&check(&params, &[packet])), ["confirmations"]);
+    }
+
+    #[test]
+    fn test_reports_header_mismatches_against_sent_packet() {
+        let packet = packet();
+        let mut other = packet.clone();
+        other.receiver = B256::repeat_byte(0x55);
+
+        let report = check(&params(&other), &[packet]);
+        assert_eq!(fields(&report), ["receiver"]);
+    }
+
+    #[test]
+    fn test_reports_packet_missing_from_transaction() {
+        let packet = packet();
+        let mut other = packet.clone();
+        other.nonce = 8;
+
+        let report = check(&params(&packet), &[]);
+        assert_eq!(fields(&report), ["packet"]);
+
+        // Among several packets, one with the assigned nonce must have been sent
+        let mut third = packet.clone();
+        third.nonce = 9;
+        third.message = Bytes::from_static(b"other");
+        let report = check(&params(&third), &[packet, other]);
+        assert_eq!(fields(&report), ["packet"]);
+    }
+
+    #[test]
+    fn test_reports_malformed_header() {
+        let packet = packet();
+        let mut params = params(&packet);
+        params.packetHeader = Bytes::from_static(&[1, 2, 3]);
+
+        let report = check(&params, &[packet]);
+        assert_eq!(fields(&report), ["packetHeader"]);
+    }
+
+    #[test]
+    fn test_match_job_assigned_rebuilds_header() {
+        let packet = packet();
+        let expected = params(&packet);
+        let job = job_assigned(&expected);
+
+        let recovered = match_job_assigned(&job, &packet).unwrap();
+        assert_eq!(recovered.packetHeader, expected.packetHeader);
+        assert!(check(&recovered, &[packet.clone()]).is_empty());
+
+        let mut other = packet.clone();
+        other.message = Bytes::from_static(b"other");
+        assert!(match_job_assigned(&job, &other).is_none());
+    }
+
+    #[test]
+    fn test_assignments_in_multi_packet_transaction() {
+        let dvn = Address::repeat_byte(0xdd);
+        let extra_dvn = Address::repeat_byte(0xde);
+        let source = ChainConfig {
+            eid: 30101,
+            chain_id: 1,
+            rpc_url: String::new(),
+            ws_url: None,
+            endpoint: ENDPOINT,
+            send_uln: Address::repeat_byte(0xaa),
+            receive_uln: Address::ZERO,
+            dvn,
+            extra_dvns: vec![extra_dvn],
+            start_block: None,
+            confirmations: Default::default(),
+        };
+
+        // Two sends to the same destination in one transaction, each assigning its job before
+        // the endpoint emits its packet, the second one to both of our DVNs
+        let first = test_packets::packet(7, 30101);
+        let second = test_packets::packet(8, 30101);
+        let first_params = params(&first);
+        let second_params = AssignJobParam {
+            confirmations: 20,
+            ..params(&second)
+        };
+        let extra_params = AssignJobParam {
+            confirmations: 30,
+            ..params(&second)
+        };
+        let logs = [
+            log(dvn, job_assigned(&first_params).encode_log_data(), 0),
+            packet_sent(codec::encode_packet(&first), 1),
+            log(dvn, job_assigned(&second_params).encode_log_data(), 2),
+            log(extra_dvn, job_assigned(&extra_params).encode_log_data(), 3),
+            packet_sent(codec::encode_packet(&second), 4),
+        ];
+
+        // Compared by the packet they assign and their confirmations
+        let assigned = |params: Option<AssignJobParam>| {
+            params.map(|params| {
+                (
+                    codec::message_id(&params.packetHeader, params.payloadHash),
+                    params.confirmations,
+                )
+            })
+        };
+        let first_id = codec::packet_message_id(&first);
+        let second_id = codec::packet_message_id(&second);
+        assert_eq!(
+            assigned(assignment_for_message(&logs, first_id, &source)),
+            Some((first_id, 15))
+        );
+        assert_eq!(
+            assigned(assignment_for_message(&logs, second_id, &source)),
+            Some((second_id, 20))
+        );
+        assert!(assignment_for_message(&logs, B256::repeat_byte(0x01), &source).is_none());
+
+        // Each event is paired with the packet of its own send
+        let extra = assignment_for_job(&job_assigned(&extra_params), &logs[3], &logs, ENDPOINT);
+        assert_eq!(assigned(extra), Some((second_id, 30)));
+        assert!(
+            assignment_for_job(&job_assigned(&second_params), &logs[0], &logs, ENDPOINT).is_none()
+        );
+        assert_eq!(packet_of_send(&logs, &logs[2], ENDPOINT), Some(second));
+        assert_eq!(packet_of_send(&logs, &logs[4], ENDPOINT), None);
+    }
+
+    #[test]
+    fn test_sent_packets_skips_invalid_packets() {
+        let packet = packet();
+        let mut spoofed = packet.clone();
+        spoofed.guid = B256::repeat_byte(0x01);
+        let logs = [
+            packet_sent(Bytes::from_static(b"malformed"), 0),
+            packet_sent(codec::encode_packet(&spoofed), 1),
+            packet_sent(codec::encode_packet(&packet), 2),
+        ];
+
+        assert_eq!(sent_packets(&logs, ENDPOINT), [packet]);
+        assert!(sent_packets(&logs, Address::ZERO).is_empty());
+    }
+
+    #[test]
+    fn test_find_assign_job_in_nested_calls() {
+        let packet = packet();
+        let dvn = Address::repeat_byte(0xdd);
+        let input = assignJobCall {
+            _param: params(&packet),
+            _options: Bytes::new(),
+        }
+        .abi_encode();
+
+        let assign_job = CallFrame {
+            to: Some(dvn),
+            input: input.clone().into(),
+            ..Default::default()
+        };
+        let send_uln = CallFrame {
+            to: Some(Address::repeat_byte(0xee)),
+            calls: vec![assign_job.clone()],
+            ..Default::default()
+        };
+        let endpoint = CallFrame {
+            to: Some(Address::repeat_byte(0xff)),
+            calls: vec![send_uln],
+            ..Default::default()
+        };
+
+        let message_id = codec::packet_message_id(&packet);
+        let recovered = find_assign_job(&endpoint, &[dvn], message_id).unwrap();
+        assert_eq!(recovered.packetHeader, params(&packet).packetHeader);
+        assert!(find_assign_job(&endpoint, &[Address::repeat_byte(0x01)], message_id).is_none());
+        assert!(find_assign_job(&endpoint, &[dvn], B256::repeat_byte(0x01)).is_none());
+
+        let reverted = CallFrame {
+            error: Some("execution reverted".into()),
+            ..assign_job
+        };
+        assert!(find_assign_job(&reverted, &[dvn], message_id).is_none());
+    }
+}
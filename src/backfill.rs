@@ -0,0 +1,273 @@
+//! Backfill of `PacketSent` and `DVNFeePaid` events missed while the DVN was offline
+
+use crate::confirmations::current_block;
+use crate::queue::Assignment;
+use crate::retry::{with_retries, RetryPolicy, Skip};
+use crate::ILayerZeroEndpointV2::PacketSent;
+use crate::SendUln302::DVNFeePaid;
+use crate::{accept_fee_paid, spawn_verification, store_packet, DvnContext};
+use alloy_primitives::{Address, B256};
+use alloy_provider::Provider;
+use alloy_rpc_types_eth::{Filter, Log};
+use alloy_sol_types::SolEvent;
+use gadget_sdk::store::LocalDatabase;
+use gadget_sdk::Error;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Retries of a backfilled event hitting temporary errors. Once they run out the backfill stops
+/// at the event's block range, while events failing for good are skipped.
+const RETRIES: RetryPolicy = RetryPolicy {
+    max_attempts: Some(5),
+    delay: Duration::from_secs(2),
+    max_delay: Duration::from_secs(30),
+};
+
+/// Backfill settings
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BackfillConfig {
+    /// Whether to backfill missed events on startup
+    pub enabled: bool,
+    /// Maximum number of blocks requested in a single `eth_getLogs` call
+    pub max_block_range: u64,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_block_range: 2_000,
+        }
+    }
+}
+
+/// Last processed block of every source chain, keyed by endpoint ID
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    db: Arc<LocalDatabase<u64>>,
+    /// Serializes read-modify-write updates of a checkpoint
+    lock: Arc<Mutex<()>>,
+}
+
+impl Checkpoints {
+    /// Checkpoints of the live event handlers kept in `dir`
+    pub fn open(dir: impl AsRef<Path>) -> Self {
+        Self::open_file(dir.as_ref().join("checkpoints.json"))
+    }
+
+    /// Backfill cursors kept in `dir`, only advanced past blocks whose events were all processed
+    pub fn open_backfill_cursors(dir: impl AsRef<Path>) -> Self {
+        Self::open_file(dir.as_ref().join("backfill_cursors.json"))
+    }
+
+    fn open_file(path: PathBuf) -> Self {
+        Self {
+            db: Arc::new(LocalDatabase::open(path)),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn get(&self, eid: u32) -> Result<Option<u64>, Error> {
+        Ok(self.db.get(&eid.to_string()))
+    }
+
+    /// Record `block` as processed for `eid`, never moving the checkpoint backwards
+    pub async fn advance(&self, eid: u32, block: u64) -> Result<(), Error> {
+        let _guard = self.lock.lock().await;
+        if self.get(eid).await?.is_some_and(|current| current >= block) {
+            return Ok(());
+        }
+
+        self.db.set(&eid.to_string(), block);
+        Ok(())
+    }
+}
+
+/// Replay every `PacketSent` and `DVNFeePaid` event emitted on the context's source chain since
+/// its backfill cursor, up to the current head, through the same handlers as live events.
+///
+/// The cursor only moves past a block range once its packets are stored and its assignments
+/// persisted, so a range interrupted by a crash or by an event that keeps failing is backfilled
+/// again on the next start. Assigned packets are verified by the job pipeline, which resumes them
+/// after a restart, so the cursor does not wait for their verification. Chains without a cursor
+/// start after the live checkpoint, or from their configured `start_block`, and are only marked
+/// as backfilled up to the current head if neither is set.
+pub async fn backfill(config: BackfillConfig, ctx: &DvnContext) -> Result<(), Error> {
+    let eid = ctx.source_eid;
+    let chain = ctx.chains.get(eid)?;
+    let head = current_block(chain).await?;
+
+    let Some(from) = backfill_start(
+        ctx.backfill_cursors.get(eid).await?,
+        ctx.checkpoints.get(eid).await?,
+        chain.config.start_block,
+    ) else {
+        return ctx.backfill_cursors.advance(eid, head).await;
+    };
+    if from > head {
+        return Ok(());
+    }
+
+    tracing::info!("Backfilling EID {} from block {} to {}", eid, from, head);
+
+    for (start, end) in block_ranges(from, head, config.max_block_range) {
+        // Packets first, so assignments in the same range find them already stored
+        for log in get_logs(
+            ctx,
+            eid,
+            chain.config.endpoint,
+            PacketSent::SIGNATURE_HASH,
+            start,
+            end,
+        )
+        .await?
+        {
+            let packet = match log.log_decode::<PacketSent>() {
+                Ok(packet_sent) => crate::convert_packet_event((packet_sent.inner.data, log)).await,
+                Err(e) => Err(Error::Client(format!("Failed to decode PacketSent: {}", e))),
+            };
+            let (packet, options, log) = match packet {
+                Ok(packet) => packet,
+                Err(e) => {
+                    tracing::warn!("Skipping invalid backfilled packet: {}", e);
+                    continue;
+                }
+            };
+
+            with_retries("backfilled packet", &RETRIES, Skip, || {
+                let (packet, options, log) = (packet.clone(), options.clone(), log.clone());
+                async move { Ok(store_packet(packet, options, log, ctx.clone()).await?) }
+            })
+            .await?;
+        }
+
+        // Assignments must be persisted before the cursor moves past their range, while their
+        // packets are verified by the job pipeline in the background
+        for log in get_logs(
+            ctx,
+            eid,
+            chain.config.send_uln,
+            DVNFeePaid::SIGNATURE_HASH,
+            start,
+            end,
+        )
+        .await?
+        {
+            let fee_paid = match log.log_decode::<DVNFeePaid>() {
+                Ok(fee_paid) => fee_paid.inner.data,
+                Err(e) => {
+                    tracing::warn!("Skipping undecodable DVNFeePaid: {}", e);
+                    continue;
+                }
+            };
+
+            let accepted = with_retries("backfilled assignment", &RETRIES, Skip, || {
+                accept_fee_paid(&fee_paid, &log, ctx)
+            })
+            .await?;
+            if let Some(Some((message_id, Assignment::Started))) = accepted {
+                spawn_verification(message_id, ctx.clone());
+            }
+        }
+
+        ctx.backfill_cursors.advance(eid, end).await?;
+    }
+
+    Ok(())
+}
+
+/// First block to backfill: the one after the backfill cursor, falling back to the one after the
+/// live checkpoint and then to the configured start block
+fn backfill_start(
+    cursor: Option<u64>,
+    checkpoint: Option<u64>,
+    start_block: Option<u64>,
+) -> Option<u64> {
+    cursor.or(checkpoint).map(|block| block + 1).or(start_block)
+}
+
+/// Consecutive ranges of at most `max_block_range` blocks covering `from..=to`
+fn block_ranges(from: u64, to: u64, max_block_range: u64) -> Vec<(u64, u64)> {
+    let max_block_range = max_block_range.max(1);
+    let mut ranges = Vec::new();
+    let mut start = from;
+    while start <= to {
+        let end = to.min(start.saturating_add(max_block_range - 1));
+        ranges.push((start, end));
+        start = end + 1;
+    }
+
+    ranges
+}
+
+async fn get_logs(
+    ctx: &DvnContext,
+    eid: u32,
+    address: Address,
+    event_signature: B256,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Vec<Log>, Error> {
+    let filter = Filter::new()
+        .address(address)
+        .event_signature(event_signature)
+        .from_block(from_block)
+        .to_block(to_block);
+
+    ctx.chains
+        .provider(eid)?
+        .get_logs(&filter)
+        .await
+        .map_err(|e| {
+            Error::Client(format!(
+                "Failed to get logs for EID {} in blocks {}..={}: {}",
+                eid, from_block, to_block, e
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_ranges() {
+        assert_eq!(block_ranges(10, 14, 2), [(10, 11), (12, 13), (14, 14)]);
+        assert_eq!(block_ranges(10, 10, 2_000), [(10, 10)]);
+        assert_eq!(block_ranges(10, 12, 0), [(10, 10), (11, 11), (12, 12)]);
+        assert!(block_ranges(11, 10, 2_000).is_empty());
+    }
+
+    #[test]
+    fn test_backfill_start() {
+        // The backfill cursor wins over the live checkpoint, which may be ahead of it
+        assert_eq!(backfill_start(Some(100), Some(500), Some(1)), Some(101));
+        // Without a cursor, pick up after the live checkpoint
+        assert_eq!(backfill_start(None, Some(500), Some(1)), Some(501));
+        assert_eq!(backfill_start(None, None, Some(1)), Some(1));
+        assert_eq!(backfill_start(None, None, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_only_advance() {
+        let dir = tempfile::tempdir().unwrap();
+        let cursors = Checkpoints::open_backfill_cursors(dir.path());
+        assert_eq!(cursors.get(30101).await.unwrap(), None);
+
+        cursors.advance(30101, 20).await.unwrap();
+        cursors.advance(30101, 10).await.unwrap();
+        assert_eq!(cursors.get(30101).await.unwrap(), Some(20));
+        assert_eq!(cursors.get(30110).await.unwrap(), None);
+
+        // Cursors are kept apart from the live checkpoints
+        assert_eq!(
+            Checkpoints::open(dir.path()).get(30101).await.unwrap(),
+            None
+        );
+        let reopened = Checkpoints::open_backfill_cursors(dir.path());
+        assert_eq!(reopened.get(30101).await.unwrap(), Some(20));
+    }
+}
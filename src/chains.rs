@@ -0,0 +1,152 @@
+//! Registry of the chains this DVN bridges between, keyed by LayerZero endpoint ID
+
+use alloy_primitives::Address;
+use alloy_provider::{Provider, ProviderBuilder, RootProvider};
+use alloy_transport::BoxTransport;
+use gadget_sdk::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{watch, Mutex};
+
+/// Provider type used for every chain in the registry
+pub type ChainProvider = RootProvider<BoxTransport>;
+
+/// Static configuration for a single chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainConfig {
+    /// LayerZero endpoint ID of the chain
+    pub eid: u32,
+    /// EVM chain ID
+    pub chain_id: u64,
+    /// HTTP RPC endpoint of the chain
+    pub rpc_url: String,
+    /// Websocket RPC endpoint, used to subscribe to new heads when set
+    #[serde(default)]
+    pub ws_url: Option<String>,
+    /// Address of the `EndpointV2` contract
+    pub endpoint: Address,
+    /// Address of the `SendUln302` library emitting `DVNFeePaid`
+    pub send_uln: Address,
+    /// Address of the `ReceiveUln302` library packets are verified against
+    pub receive_uln: Address,
+    /// Address of our deployed `LayerZeroDVNInstance`
+    pub dvn: Address,
+    /// Other DVN contracts we operate on this chain, e.g. earlier deployments still listed in
+    /// OApp configs
+    #[serde(default)]
+    pub extra_dvns: Vec<Address>,
+    /// Block to start backfilling from when the chain has no checkpoint yet
+    #[serde(default)]
+    pub start_block: Option<u64>,
+    /// Confirmation policy for packets sent from this chain
+    #[serde(default)]
+    pub confirmations: ConfirmationPolicy,
+}
+
+impl ChainConfig {
+    /// Every DVN contract address we operate on this chain
+    pub fn dvns(&self) -> impl Iterator<Item = &Address> {
+        std::iter::once(&self.dvn).chain(&self.extra_dvns)
+    }
+}
+
+/// How long to wait before a packet sent from a chain is considered final
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ConfirmationPolicy {
+    /// Minimum number of block confirmations a job must request; jobs asking for fewer are
+    /// refused
+    pub min_confirmations: u64,
+    /// Maximum number of block confirmations a job may request; jobs asking for more are refused,
+    /// as they would hold their packet for longer than the operator is willing to track it
+    #[serde(default)]
+    pub max_confirmations: Option<u64>,
+    /// Interval between block number polls, in seconds
+    pub poll_interval_secs: u64,
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self {
+            min_confirmations: 1,
+            max_confirmations: None,
+            poll_interval_secs: 1,
+        }
+    }
+}
+
+/// A configured chain along with its provider
+#[derive(Debug, Clone)]
+pub struct Chain {
+    pub config: ChainConfig,
+    pub provider: ChainProvider,
+    /// Latest head from the chain's block subscription, shared by every job waiting for
+    /// confirmations and started by the first of them
+    pub(crate) heads: Arc<Mutex<Option<watch::Receiver<u64>>>>,
+}
+
+/// Chains known to this DVN, keyed by LayerZero endpoint ID
+#[derive(Debug, Clone, Default)]
+pub struct ChainRegistry {
+    chains: HashMap<u32, Chain>,
+}
+
+impl ChainRegistry {
+    /// Connect to every configured chain, failing when an RPC endpoint serves a chain other than
+    /// the configured `chain_id`
+    pub async fn new(configs: impl IntoIterator<Item = ChainConfig>) -> Result<Self, Error> {
+        let mut chains = HashMap::new();
+
+        for config in configs {
+            let url = config.rpc_url.parse().map_err(|e| {
+                Error::Client(format!("Invalid RPC URL for EID {}: {}", config.eid, e))
+            })?;
+            let provider = ProviderBuilder::new().on_http(url).boxed();
+
+            let chain_id = provider.get_chain_id().await.map_err(|e| {
+                Error::Client(format!(
+                    "Failed to get chain ID for EID {}: {}",
+                    config.eid, e
+                ))
+            })?;
+            if chain_id != config.chain_id {
+                return Err(Error::Client(format!(
+                    "RPC endpoint for EID {} serves chain {}, but chain {} is configured",
+                    config.eid, chain_id, config.chain_id
+                )));
+            }
+
+            if chains
+                .insert(
+                    config.eid,
+                    Chain {
+                        config,
+                        provider,
+                        heads: Default::default(),
+                    },
+                )
+                .is_some()
+            {
+                return Err(Error::Client("Duplicate EID in chain configuration".into()));
+            }
+        }
+
+        Ok(Self { chains })
+    }
+
+    /// Look up a chain by its LayerZero endpoint ID
+    pub fn get(&self, eid: u32) -> Result<&Chain, Error> {
+        self.chains
+            .get(&eid)
+            .ok_or_else(|| Error::Client(format!("No chain configured for EID {}", eid)))
+    }
+
+    /// Provider for the chain with the given endpoint ID
+    pub fn provider(&self, eid: u32) -> Result<ChainProvider, Error> {
+        Ok(self.get(eid)?.provider.clone())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Chain> {
+        self.chains.values()
+    }
+}
@@ -0,0 +1,253 @@
+//! Operator commands for inspecting and repairing the DVN's local stores, and for verifying
+//! packets by hand.
+//!
+//! The stores are plain files loaded by the DVN on startup, so commands that modify them
+//! (`packets replay` and `packets purge`) must be run while the DVN is stopped. The sled backend
+//! locks its database while the DVN runs, so with it every `packets` command needs the DVN
+//! stopped, and fails with an error saying so otherwise.
+
+use crate::config::DvnConfig;
+use crate::manual::verify_transaction;
+use crate::queue::{JobQueue, JobRecord};
+use crate::store::{self, PacketStore, StoredPacket};
+use crate::DvnContext;
+use alloy_primitives::B256;
+use gadget_sdk::config::ContextConfig;
+use gadget_sdk::Error;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+
+/// Commands run by operators instead of the DVN itself
+#[derive(Debug, StructOpt)]
+#[structopt(name = "layerzero-dvn-blueprint-template")]
+pub enum OperatorCommand {
+    /// Inspect and manage stored packets
+    Packets {
+        /// Directory holding the DVN's stores
+        #[structopt(long, env = "DATA_DIR", parse(from_os_str))]
+        data_dir: PathBuf,
+        /// DVN configuration file, naming the storage backend
+        #[structopt(long, env = "DVN_CONFIG", parse(from_os_str))]
+        config: PathBuf,
+        #[structopt(subcommand)]
+        command: PacketsCommand,
+    },
+    /// Verify the packet assigned to us in a source chain transaction, printing every step.
+    /// Gadget settings such as the keystore are read from the same environment variables as `run`.
+    VerifyTx {
+        /// Endpoint ID of the source chain
+        #[structopt(long)]
+        src_eid: u32,
+        /// Hash of the transaction that sent the packet
+        #[structopt(long)]
+        tx: B256,
+        /// Stop before sending the verification to the destination chain
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum PacketsCommand {
+    /// List every stored packet along with the state of its job
+    List,
+    /// Show a stored packet and its job
+    Show { message_id: B256 },
+    /// Requeue the job of a packet, so it is verified again when the DVN next starts
+    Replay { message_id: B256 },
+    /// Remove packets stored longer ago than the given age, such as `30m`, `12h` or `7d`.
+    /// Packets whose jobs are still in progress are kept.
+    Purge {
+        #[structopt(long, parse(try_from_str = parse_age))]
+        older_than: Duration,
+    },
+}
+
+impl OperatorCommand {
+    /// Names of the operator commands, used to tell them apart from the gadget's own CLI
+    const NAMES: &'static [&'static str] = &["packets", "verify-tx"];
+
+    /// Parse an operator command from the process arguments, if they name one
+    pub fn from_args_if_present() -> Option<Self> {
+        let command = std::env::args().nth(1)?;
+        Self::NAMES
+            .contains(&command.as_str())
+            .then(Self::from_args)
+    }
+
+    pub async fn execute(self) -> Result<(), Error> {
+        match self {
+            Self::Packets {
+                data_dir,
+                config,
+                command,
+            } => {
+                let packets = store::open(DvnConfig::load(config)?.storage, &data_dir)?;
+                let stores = Stores {
+                    jobs: JobQueue::new(packets.clone()),
+                    packets,
+                };
+                stores.execute(command).await
+            }
+            Self::VerifyTx {
+                src_eid,
+                tx,
+                dry_run,
+            } => {
+                let env = gadget_sdk::config::load(ContextConfig::from_iter([
+                    "layerzero-dvn-blueprint-template",
+                    "run",
+                ]))
+                .map_err(|e| Error::Client(format!("Failed to load gadget config: {}", e)))?;
+                let dvn_config = DvnConfig::from_env()?;
+
+                // Work in a fresh scratch directory, leaving the running DVN's stores untouched
+                // and checking the packet against the current chain state on every run
+                let scratch_dir = tempfile::Builder::new()
+                    .prefix("dvn-verify-tx-")
+                    .tempdir()
+                    .map_err(|e| {
+                        Error::Client(format!("Failed to create scratch directory: {}", e))
+                    })?;
+                let ctx = DvnContext {
+                    source_eid: src_eid,
+                    ..DvnContext::new(env, &dvn_config, scratch_dir.path()).await?
+                };
+
+                verify_transaction(tx, dry_run, &ctx).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Stores {
+    packets: Arc<dyn PacketStore>,
+    jobs: JobQueue,
+}
+
+impl Stores {
+    async fn execute(&self, command: PacketsCommand) -> Result<(), Error> {
+        match command {
+            PacketsCommand::List => self.list().await,
+            PacketsCommand::Show { message_id } => self.show(message_id).await,
+            PacketsCommand::Replay { message_id } => self.replay(message_id).await,
+            PacketsCommand::Purge { older_than } => self.purge(older_than).await,
+        }
+    }
+
+    async fn list(&self) -> Result<(), Error> {
+        for record in self.jobs.all().await? {
+            match self.packets.get(record.message_id).await? {
+                Some(stored) => println!(
+                    "{} {:?} src={} dst={} nonce={} block={}",
+                    record.message_id,
+                    record.state,
+                    stored.packet.srcEid,
+                    stored.packet.dstEid,
+                    stored.packet.nonce,
+                    stored.block_number
+                ),
+                None => println!(
+                    "{} {:?} (packet not stored)",
+                    record.message_id, record.state
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn show(&self, message_id: B256) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct Details {
+            packet: Option<StoredPacket>,
+            job: Option<JobRecord>,
+        }
+
+        let details = Details {
+            packet: self.packets.get(message_id).await?,
+            job: self.jobs.get(message_id).await?,
+        };
+        if details.packet.is_none() && details.job.is_none() {
+            return Err(Error::Client(format!("Unknown packet {}", message_id)));
+        }
+
+        let json = serde_json::to_string_pretty(&details)
+            .map_err(|e| Error::Client(format!("Failed to serialize packet: {}", e)))?;
+        println!("{}", json);
+        Ok(())
+    }
+
+    async fn replay(&self, message_id: B256) -> Result<(), Error> {
+        if self.packets.get(message_id).await?.is_none() {
+            return Err(Error::Client(format!(
+                "Packet {} is not stored",
+                message_id
+            )));
+        }
+
+        let record = self.jobs.requeue(message_id).await?;
+        println!("{} requeued as {:?}", message_id, record.state);
+        Ok(())
+    }
+
+    async fn purge(&self, older_than: Duration) -> Result<(), Error> {
+        let cutoff = SystemTime::now()
+            .checked_sub(older_than)
+            .unwrap_or(UNIX_EPOCH)
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        let mut purged = 0;
+        for record in self.jobs.all().await? {
+            let Some(stored) = self.packets.get(record.message_id).await? else {
+                continue;
+            };
+            if stored.timestamp >= cutoff {
+                continue;
+            }
+            if record.state.is_in_progress() {
+                println!(
+                    "Keeping {}, its job is still {:?}",
+                    record.message_id, record.state
+                );
+                continue;
+            }
+
+            self.packets.remove(record.message_id).await?;
+            self.jobs.remove(record.message_id).await?;
+            purged += 1;
+        }
+
+        println!("Purged {} packets", purged);
+        Ok(())
+    }
+}
+
+/// Parse an age such as `90s`, `30m`, `12h` or `7d`, defaulting to seconds without a unit
+fn parse_age(age: &str) -> Result<Duration, String> {
+    let (value, unit) = match age.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => age.split_at(index),
+        None => (age, "s"),
+    };
+    let value = value
+        .parse::<u64>()
+        .map_err(|e| format!("Invalid age {}: {}", age, e))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Invalid age unit in {}", age)),
+    };
+
+    let seconds = value
+        .checked_mul(seconds)
+        .ok_or_else(|| format!("Age {} is too large", age))?;
+
+    Ok(Duration::from_secs(seconds))
+}
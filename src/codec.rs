@@ -0,0 +1,349 @@
+//! Encoding of LayerZero V2 packets, matching `PacketV1Codec` and `DVNAdapterMessageCodec`
+
+use crate::ISendLib::Packet;
+use alloy_primitives::{keccak256, Address, Bytes, B256};
+use gadget_sdk::Error;
+
+/// Version byte of PacketV1 encoded packets
+pub const PACKET_VERSION: u8 = 1;
+
+// PacketV1 layout:
+// version (uint8) || nonce (uint64) || srcEid (uint32) || sender (bytes32) || dstEid (uint32) ||
+// receiver (bytes32) || guid (bytes32) || message (bytes)
+const NONCE_OFFSET: usize = 1;
+const SRC_EID_OFFSET: usize = 9;
+const SENDER_OFFSET: usize = 13;
+const DST_EID_OFFSET: usize = 45;
+const RECEIVER_OFFSET: usize = 49;
+const GUID_OFFSET: usize = 81;
+const MESSAGE_OFFSET: usize = 113;
+
+/// Size of an encoded packet header
+pub const PACKET_HEADER_SIZE: usize = GUID_OFFSET;
+
+/// Fields of a PacketV1 header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHeader {
+    pub nonce: u64,
+    pub src_eid: u32,
+    pub sender: B256,
+    pub dst_eid: u32,
+    pub receiver: B256,
+}
+
+impl PacketHeader {
+    pub fn encode(&self) -> Bytes {
+        let mut header = Vec::with_capacity(PACKET_HEADER_SIZE);
+        header.push(PACKET_VERSION);
+        header.extend_from_slice(&self.nonce.to_be_bytes());
+        header.extend_from_slice(&self.src_eid.to_be_bytes());
+        header.extend_from_slice(self.sender.as_slice());
+        header.extend_from_slice(&self.dst_eid.to_be_bytes());
+        header.extend_from_slice(self.receiver.as_slice());
+        header.into()
+    }
+
+    /// Decode the header at the start of `bytes`, which may be a bare header or a full packet
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < PACKET_HEADER_SIZE {
+            return Err(Error::Client(format!(
+                "Packet header too short: {} bytes",
+                bytes.len()
+            )));
+        }
+        if bytes[0] != PACKET_VERSION {
+            return Err(Error::Client(format!(
+                "Unsupported packet version {}",
+                bytes[0]
+            )));
+        }
+
+        Ok(Self {
+            nonce: u64::from_be_bytes(read_array(bytes, NONCE_OFFSET)),
+            src_eid: u32::from_be_bytes(read_array(bytes, SRC_EID_OFFSET)),
+            sender: B256::from(read_array::<32>(bytes, SENDER_OFFSET)),
+            dst_eid: u32::from_be_bytes(read_array(bytes, DST_EID_OFFSET)),
+            receiver: B256::from(read_array::<32>(bytes, RECEIVER_OFFSET)),
+        })
+    }
+
+    /// Sender as an EVM address
+    pub fn sender_address(&self) -> Address {
+        Address::from_word(self.sender)
+    }
+
+    /// GUID the endpoint assigns to a packet with this header, matching `GUID.generate`:
+    /// keccak256(nonce || srcEid || sender || dstEid || receiver)
+    pub fn guid(&self) -> B256 {
+        let mut data = Vec::with_capacity(8 + 4 + 32 + 4 + 32);
+        data.extend_from_slice(&self.nonce.to_be_bytes());
+        data.extend_from_slice(&self.src_eid.to_be_bytes());
+        data.extend_from_slice(self.sender.as_slice());
+        data.extend_from_slice(&self.dst_eid.to_be_bytes());
+        data.extend_from_slice(self.receiver.as_slice());
+        keccak256(data)
+    }
+}
+
+impl From<&Packet> for PacketHeader {
+    fn from(packet: &Packet) -> Self {
+        Self {
+            nonce: packet.nonce,
+            src_eid: packet.srcEid,
+            sender: packet.sender.into_word(),
+            dst_eid: packet.dstEid,
+            receiver: packet.receiver,
+        }
+    }
+}
+
+/// Check that the GUID carried by `packet` is the one the endpoint would have generated for it
+pub fn validate_guid(packet: &Packet) -> Result<(), Error> {
+    let expected = PacketHeader::from(packet).guid();
+    if packet.guid != expected {
+        return Err(Error::Client(format!(
+            "GUID mismatch for nonce {} from EID {}: packet has {}, expected {}",
+            packet.nonce, packet.srcEid, packet.guid, expected
+        )));
+    }
+
+    Ok(())
+}
+
+/// Encode `packet` as emitted in `PacketSent.encodedPayload`
+pub fn encode_packet(packet: &Packet) -> Bytes {
+    let mut encoded = PacketHeader::from(packet).encode().to_vec();
+    encoded.extend_from_slice(&encode_payload(packet));
+    encoded.into()
+}
+
+/// Decode a packet from `PacketSent.encodedPayload`
+pub fn decode_packet(bytes: &[u8]) -> Result<Packet, Error> {
+    let header = PacketHeader::decode(bytes)?;
+    if bytes.len() < MESSAGE_OFFSET {
+        return Err(Error::Client(format!(
+            "Packet too short: {} bytes",
+            bytes.len()
+        )));
+    }
+
+    Ok(Packet {
+        nonce: header.nonce,
+        srcEid: header.src_eid,
+        sender: header.sender_address(),
+        dstEid: header.dst_eid,
+        receiver: header.receiver,
+        guid: B256::from(read_array::<32>(bytes, GUID_OFFSET)),
+        message: Bytes::copy_from_slice(&bytes[MESSAGE_OFFSET..]),
+    })
+}
+
+/// Encode the packet payload: guid || message
+pub fn encode_payload(packet: &Packet) -> Bytes {
+    let mut payload = Vec::with_capacity(32 + packet.message.len());
+    payload.extend_from_slice(packet.guid.as_slice());
+    payload.extend_from_slice(&packet.message);
+    payload.into()
+}
+
+/// Payload hash as stored by the ULN: keccak256(guid || message)
+pub fn payload_hash(packet: &Packet) -> B256 {
+    keccak256(encode_payload(packet))
+}
+
+/// Message ID of a packet: keccak256(header || payload hash)
+pub fn message_id(header: &[u8], payload_hash: B256) -> B256 {
+    let mut message_data = Vec::with_capacity(header.len() + 32);
+    message_data.extend_from_slice(header);
+    message_data.extend_from_slice(payload_hash.as_slice());
+    keccak256(message_data)
+}
+
+/// Message ID of `packet`
+pub fn packet_message_id(packet: &Packet) -> B256 {
+    message_id(&PacketHeader::from(packet).encode(), payload_hash(packet))
+}
+
+/// Data the security verifiers attest to: packet header || payload hash, the preimage of the
+/// message ID
+pub fn encode_verification_data(packet: &Packet) -> Bytes {
+    let mut data = PacketHeader::from(packet).encode().to_vec();
+    data.extend_from_slice(payload_hash(packet).as_slice());
+    data.into()
+}
+
+/// Encode the message expected by `verifyMessageHash`, matching `DVNAdapterMessageCodec.encode`:
+/// receiveLib (bytes32) || packet header || payload hash
+pub fn encode_adapter_message(receive_lib: Address, packet: &Packet) -> Bytes {
+    let header = PacketHeader::from(packet).encode();
+
+    let mut message = Vec::with_capacity(32 + header.len() + 32);
+    message.extend_from_slice(receive_lib.into_word().as_slice());
+    message.extend_from_slice(&header);
+    message.extend_from_slice(payload_hash(packet).as_slice());
+    message.into()
+}
+
+fn read_array<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    let mut array = [0u8; N];
+    array.copy_from_slice(&bytes[offset..offset + N]);
+    array
+}
+
+/// Packets shared by the tests of other modules
+#[cfg(test)]
+pub(crate) mod test_packets {
+    use super::*;
+    use crate::store::StoredPacket;
+
+    /// A packet with the given nonce sent from `src_eid` to EID 30110, carrying the GUID the
+    /// endpoint generates for it
+    pub(crate) fn packet(nonce: u64, src_eid: u32) -> Packet {
+        let mut packet = Packet {
+            nonce,
+            srcEid: src_eid,
+            sender: Address::repeat_byte(0x11),
+            dstEid: 30110,
+            receiver: B256::repeat_byte(0x22),
+            guid: B256::ZERO,
+            message: Bytes::from_static(b"hello"),
+        };
+        packet.guid = PacketHeader::from(&packet).guid();
+        packet
+    }
+
+    /// [`packet`] as stored from a `PacketSent` log in block `nonce`, along with its message ID
+    pub(crate) fn stored_packet(nonce: u64, src_eid: u32) -> (B256, StoredPacket) {
+        let packet = packet(nonce, src_eid);
+        let message_id = packet_message_id(&packet);
+        let stored = StoredPacket {
+            packet,
+            options: Bytes::new(),
+            block_number: nonce,
+            block_hash: B256::repeat_byte(0x33),
+            transaction_hash: B256::repeat_byte(0x44),
+            timestamp: 0,
+        };
+        (message_id, stored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    /// A packet encoded by LayerZero's `PacketV1Codec`, see
+    /// `contracts/script/GeneratePacketFixtures.s.sol`
+    #[derive(Debug, Deserialize)]
+    struct Fixture {
+        nonce: u64,
+        src_eid: u32,
+        sender: Address,
+        dst_eid: u32,
+        receiver: B256,
+        guid: B256,
+        message: Bytes,
+        encoded: Bytes,
+        header: Bytes,
+        payload_hash: B256,
+        message_id: B256,
+    }
+
+    impl Fixture {
+        fn packet(&self) -> Packet {
+            Packet {
+                nonce: self.nonce,
+                srcEid: self.src_eid,
+                sender: self.sender,
+                dstEid: self.dst_eid,
+                receiver: self.receiver,
+                guid: self.guid,
+                message: self.message.clone(),
+            }
+        }
+    }
+
+    fn fixtures() -> BTreeMap<String, Fixture> {
+        serde_json::from_str(include_str!("../fixtures/packet_v1.json")).unwrap()
+    }
+
+    #[test]
+    fn test_encode_matches_packet_v1_codec() {
+        for (name, fixture) in fixtures() {
+            let packet = fixture.packet();
+            assert_eq!(encode_packet(&packet), fixture.encoded, "{}", name);
+            assert_eq!(
+                PacketHeader::from(&packet).encode(),
+                fixture.header,
+                "{}",
+                name
+            );
+            assert_eq!(payload_hash(&packet), fixture.payload_hash, "{}", name);
+            assert_eq!(packet_message_id(&packet), fixture.message_id, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_verification_data_is_message_id_preimage() {
+        for (name, fixture) in fixtures() {
+            let data = encode_verification_data(&fixture.packet());
+            assert_eq!(data[..PACKET_HEADER_SIZE], fixture.header[..], "{}", name);
+            assert_eq!(
+                data[PACKET_HEADER_SIZE..],
+                fixture.payload_hash[..],
+                "{}",
+                name
+            );
+            assert_eq!(keccak256(&data), fixture.message_id, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        for (name, fixture) in fixtures() {
+            let packet = decode_packet(&fixture.encoded).unwrap();
+            assert_eq!(packet, fixture.packet(), "{}", name);
+            assert_eq!(encode_packet(&packet), fixture.encoded, "{}", name);
+
+            let header = PacketHeader::decode(&fixture.header).unwrap();
+            assert_eq!(header, PacketHeader::from(&packet), "{}", name);
+            assert_eq!(header.encode(), fixture.header, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_guid_matches_endpoint() {
+        for (name, fixture) in fixtures() {
+            let packet = fixture.packet();
+            assert_eq!(PacketHeader::from(&packet).guid(), fixture.guid, "{}", name);
+            assert!(validate_guid(&packet).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_validate_guid_rejects_spoofed_packets() {
+        let fixture = &fixtures()["case0"];
+
+        let mut spoofed_guid = fixture.packet();
+        spoofed_guid.guid = B256::repeat_byte(0xaa);
+        assert!(validate_guid(&spoofed_guid).is_err());
+
+        let mut spoofed_receiver = fixture.packet();
+        spoofed_receiver.receiver = B256::repeat_byte(0xbb);
+        assert!(validate_guid(&spoofed_receiver).is_err());
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_packets() {
+        let fixture = &fixtures()["case0"];
+
+        assert!(decode_packet(&fixture.encoded[..MESSAGE_OFFSET - 1]).is_err());
+        assert!(PacketHeader::decode(&fixture.header[..PACKET_HEADER_SIZE - 1]).is_err());
+
+        let mut wrong_version = fixture.encoded.to_vec();
+        wrong_version[0] = 2;
+        assert!(decode_packet(&wrong_version).is_err());
+    }
+}
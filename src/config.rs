@@ -0,0 +1,53 @@
+//! Operator configuration for the DVN
+
+use crate::backfill::BackfillConfig;
+use crate::chains::ChainConfig;
+use crate::retention::RetentionConfig;
+use crate::security::SecurityType;
+use crate::store::StorageBackend;
+use gadget_sdk::Error;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::Path;
+
+/// Environment variable pointing at the DVN configuration file
+pub const DVN_CONFIG_ENV: &str = "DVN_CONFIG";
+
+/// DVN configuration, loaded from a JSON file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DvnConfig {
+    /// Chains the DVN listens on and verifies to
+    pub chains: Vec<ChainConfig>,
+    /// Security verification performed before verifying a packet
+    pub security_type: SecurityType,
+    /// Backfill of events missed while the DVN was offline
+    #[serde(default)]
+    pub backfill: BackfillConfig,
+    /// Backend packets are stored in
+    #[serde(default)]
+    pub storage: StorageBackend,
+    /// Retention of stored packets
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    /// Address the Prometheus metrics endpoint listens on, if any
+    #[serde(default)]
+    pub metrics_addr: Option<SocketAddr>,
+}
+
+impl DvnConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::Client(format!("Failed to read {}: {}", path.display(), e)))?;
+
+        serde_json::from_str(&contents)
+            .map_err(|e| Error::Client(format!("Failed to parse DVN config: {}", e)))
+    }
+
+    /// Load the configuration from the file named by [`DVN_CONFIG_ENV`]
+    pub fn from_env() -> Result<Self, Error> {
+        let path = std::env::var(DVN_CONFIG_ENV)
+            .map_err(|_| Error::Client(format!("{} is not set", DVN_CONFIG_ENV)))?;
+        Self::load(path)
+    }
+}
@@ -0,0 +1,294 @@
+//! Tracking of source chain confirmations for sent packets
+
+use crate::chains::Chain;
+use alloy_primitives::B256;
+use alloy_provider::{Provider, ProviderBuilder, WsConnect};
+use alloy_rpc_types_eth::BlockNumberOrTag;
+use futures::stream::{BoxStream, StreamExt};
+use gadget_sdk::Error;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Current block number of `chain`
+pub async fn current_block(chain: &Chain) -> Result<u64, Error> {
+    chain.provider.get_block_number().await.map_err(|e| {
+        Error::Client(format!(
+            "Failed to get block number for EID {}: {}",
+            chain.config.eid, e
+        ))
+    })
+}
+
+/// Wait until the block at `packet_block` has at least `required_confirmations` blocks on top of
+/// it, returning the head that satisfied the requirement.
+///
+/// Waiters on the same chain share a single block subscription, see [`subscribe_heads`].
+pub async fn wait_for_confirmations(
+    chain: &Chain,
+    packet_block: u64,
+    required_confirmations: u64,
+) -> Result<u64, Error> {
+    let is_confirmed = |head: u64| head.saturating_sub(packet_block) >= required_confirmations;
+
+    let head = current_block(chain).await?;
+    if is_confirmed(head) {
+        return Ok(head);
+    }
+
+    tracing::debug!(
+        "Waiting for {} confirmations of block {} on EID {} (head {})",
+        required_confirmations,
+        packet_block,
+        chain.config.eid,
+        head
+    );
+
+    let mut heads = subscribe_heads(chain).await?;
+    loop {
+        let head = *heads.borrow_and_update();
+        if is_confirmed(head) {
+            return Ok(head);
+        }
+        if heads.changed().await.is_err() {
+            break;
+        }
+    }
+
+    Err(Error::Client(format!(
+        "New head stream for EID {} ended",
+        chain.config.eid
+    )))
+}
+
+/// Latest head of `chain`, updated by a block subscription shared between all callers.
+///
+/// The subscription is started on first use, and again once its stream has ended. New heads are
+/// taken from a `newHeads` subscription when the chain has a websocket endpoint configured, and
+/// from a polled block filter otherwise.
+async fn subscribe_heads(chain: &Chain) -> Result<watch::Receiver<u64>, Error> {
+    let mut heads = chain.heads.lock().await;
+    if let Some(receiver) = heads.as_ref() {
+        // The sender is dropped once the stream ends
+        if receiver.has_changed().is_ok() {
+            return Ok(receiver.clone());
+        }
+    }
+
+    let mut stream = head_stream(chain).await?;
+    let (sender, receiver) = watch::channel(current_block(chain).await?);
+    let eid = chain.config.eid;
+    tokio::spawn(async move {
+        while let Some(head) = stream.next().await {
+            sender.send_replace(head);
+        }
+        tracing::warn!("New head stream for EID {} ended", eid);
+    });
+
+    *heads = Some(receiver.clone());
+    Ok(receiver)
+}
+
+/// Stream of new head numbers of `chain`
+async fn head_stream(chain: &Chain) -> Result<BoxStream<'static, u64>, Error> {
+    match &chain.config.ws_url {
+        Some(ws_url) => {
+            let provider = ProviderBuilder::new()
+                .on_ws(WsConnect::new(ws_url.clone()))
+                .await
+                .map_err(|e| Error::Client(format!("Failed to connect to {}: {}", ws_url, e)))?;
+            let heads = provider
+                .subscribe_blocks()
+                .await
+                .map_err(|e| Error::Client(format!("Failed to subscribe to new heads: {}", e)))?
+                .into_stream();
+
+            // The subscription ends with the websocket connection, so the stream owns it
+            Ok(heads
+                .map(move |block| {
+                    let _ = &provider;
+                    block.header.number.unwrap_or_default()
+                })
+                .boxed())
+        }
+        None => {
+            let poll_interval =
+                Duration::from_secs(chain.config.confirmations.poll_interval_secs.max(1));
+            let heads = chain
+                .provider
+                .watch_blocks()
+                .await
+                .map_err(|e| Error::Client(format!("Failed to watch new blocks: {}", e)))?
+                .with_poll_interval(poll_interval)
+                .into_stream();
+
+            let provider = chain.provider.clone();
+            Ok(heads
+                .filter_map(move |_| {
+                    let provider = provider.clone();
+                    async move { provider.get_block_number().await.ok() }
+                })
+                .boxed())
+        }
+    }
+}
+
+/// Whether a source chain log is still part of the canonical chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketInclusion {
+    /// The block containing the log is still canonical
+    Canonical,
+    /// The transaction was reorged into a different block
+    Reincluded { block_number: u64, block_hash: B256 },
+    /// The transaction is no longer on the canonical chain, and the block that replaced its block
+    /// is final
+    Orphaned,
+}
+
+/// Check that the block at `block_number` still has hash `block_hash`, and if it does not, where
+/// the transaction `transaction_hash` ended up.
+///
+/// A missing receipt is often a lagging or load-balanced node rather than a reorg, so the packet is
+/// only reported as orphaned once another block at its height has `required_confirmations` on top
+/// of it. Until then, an error is returned so the check is retried.
+pub async fn packet_inclusion(
+    chain: &Chain,
+    block_number: u64,
+    block_hash: B256,
+    transaction_hash: B256,
+    required_confirmations: u64,
+) -> Result<PacketInclusion, Error> {
+    let canonical_hash = chain
+        .provider
+        .get_block_by_number(BlockNumberOrTag::Number(block_number), false)
+        .await
+        .map_err(|e| Error::Client(format!("Failed to get block {}: {}", block_number, e)))?
+        .and_then(|block| block.header.hash);
+
+    if canonical_hash == Some(block_hash) {
+        return Ok(PacketInclusion::Canonical);
+    }
+
+    let receipt_block = chain
+        .provider
+        .get_transaction_receipt(transaction_hash)
+        .await
+        .map_err(|e| {
+            Error::Client(format!(
+                "Failed to get receipt for {}: {}",
+                transaction_hash, e
+            ))
+        })?
+        .and_then(|r| r.block_number.zip(r.block_hash));
+    let head = current_block(chain).await?;
+
+    inclusion(
+        block_number,
+        block_hash,
+        canonical_hash,
+        receipt_block,
+        head,
+        required_confirmations,
+    )
+    .map_err(|()| {
+        Error::Client(format!(
+            "Receipt for {} not found while block {} is not final",
+            transaction_hash, block_number
+        ))
+    })
+}
+
+/// Where the packet logged in `block_hash` at `block_number` stands, given the hash of the
+/// canonical block at that height, the block the receipt of its transaction points at and the
+/// current head. Fails while it cannot be told apart from a lagging node.
+fn inclusion(
+    block_number: u64,
+    block_hash: B256,
+    canonical_hash: Option<B256>,
+    receipt_block: Option<(u64, B256)>,
+    head: u64,
+    required_confirmations: u64,
+) -> Result<PacketInclusion, ()> {
+    if canonical_hash == Some(block_hash) {
+        return Ok(PacketInclusion::Canonical);
+    }
+
+    match receipt_block {
+        // The node may not have served the block yet, but the receipt still points at it
+        Some((_, receipt_block_hash)) if receipt_block_hash == block_hash => {
+            Ok(PacketInclusion::Canonical)
+        }
+        Some((block_number, block_hash)) => Ok(PacketInclusion::Reincluded {
+            block_number,
+            block_hash,
+        }),
+        None => {
+            let replaced = canonical_hash.is_some()
+                && head.saturating_sub(block_number) >= required_confirmations.max(1);
+            if !replaced {
+                return Err(());
+            }
+
+            Ok(PacketInclusion::Orphaned)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: u64 = 100;
+    const HASH: B256 = B256::repeat_byte(0x01);
+    const OTHER_HASH: B256 = B256::repeat_byte(0x02);
+
+    #[test]
+    fn test_canonical() {
+        assert_eq!(
+            inclusion(BLOCK, HASH, Some(HASH), None, BLOCK, 10),
+            Ok(PacketInclusion::Canonical)
+        );
+
+        // A node lagging behind the receipt's block
+        assert_eq!(
+            inclusion(BLOCK, HASH, None, Some((BLOCK, HASH)), BLOCK, 10),
+            Ok(PacketInclusion::Canonical)
+        );
+    }
+
+    #[test]
+    fn test_reincluded() {
+        assert_eq!(
+            inclusion(
+                BLOCK,
+                HASH,
+                Some(OTHER_HASH),
+                Some((BLOCK + 1, OTHER_HASH)),
+                BLOCK + 1,
+                10
+            ),
+            Ok(PacketInclusion::Reincluded {
+                block_number: BLOCK + 1,
+                block_hash: OTHER_HASH,
+            })
+        );
+    }
+
+    #[test]
+    fn test_orphaned_once_replacement_is_final() {
+        assert_eq!(
+            inclusion(BLOCK, HASH, Some(OTHER_HASH), None, BLOCK + 10, 10),
+            Ok(PacketInclusion::Orphaned)
+        );
+
+        // Until then the receipt may just be missing from the node
+        assert!(inclusion(BLOCK, HASH, Some(OTHER_HASH), None, BLOCK + 9, 10).is_err());
+        assert!(inclusion(BLOCK, HASH, None, None, BLOCK + 100, 10).is_err());
+
+        // At least one block must build on the replacement
+        assert!(inclusion(BLOCK, HASH, Some(OTHER_HASH), None, BLOCK, 0).is_err());
+        assert_eq!(
+            inclusion(BLOCK, HASH, Some(OTHER_HASH), None, BLOCK + 1, 0),
+            Ok(PacketInclusion::Orphaned)
+        );
+    }
+}
@@ -1,32 +1,48 @@
 use crate::SendUln302::{self, DVNFeePaid};
 use crate::{
-    security::{SecurityType, SecurityVerifier, VerificationContext},
-    ILayerZeroDVN::{self, DVNFeePaid},
+    assignment::{
+        assignment_for_job, check_assignment, packet_of_send, receipt_logs, recover_assignment,
+        sent_packets,
+    },
+    backfill::Checkpoints,
+    chains::{Chain, ChainConfig, ChainRegistry},
+    codec::{self, PacketHeader},
+    config::DvnConfig,
+    confirmations::{packet_inclusion, wait_for_confirmations, PacketInclusion},
+    options::PacketOptions,
+    queue::{Assignment, DvnSelection, JobQueue, JobRecord, JobState},
+    retry::{with_retries, Propagate, RetryPolicy},
+    security::{
+        ProverClient, ProverError, SecurityType, SecurityVerifier, VerificationContext,
+        ZkProofVerifier,
+    },
+    store::{self, PacketStore, StoredPacket},
+    submitter::{SubmitError, VerificationSubmitter},
+    ILayerZeroDVN::AssignJobParam,
     ILayerZeroEndpointV2::{self, PacketSent},
     ISendLib::Packet,
-    ILAYER_ZERO_ENDPOINT_V2_ABI_STRING, ILAYER_ZERO_SEND_ULN_BASE_ABI_STRING,
+    LayerZeroDVNInstance::{self, JobAssigned},
+    ReceiveUln302, ILAYER_ZERO_ENDPOINT_V2_ABI_STRING, ILAYER_ZERO_SEND_ULN_BASE_ABI_STRING,
+    LAYER_ZERO_DVN_INSTANCE_ABI_STRING,
 };
 use alloy_primitives::keccak256;
-use alloy_primitives::{Address, Bytes, U256};
-use alloy_sol_types::sol;
-use alloy_sol_types::SolType;
+use alloy_primitives::{Address, Bytes, B256};
+use alloy_provider::Provider;
 use gadget_sdk::contexts::{EVMProviderContext, KeystoreContext, TangleClientContext};
-use gadget_sdk::store::LocalDatabase;
 use gadget_sdk::{
     config::StdGadgetConfiguration, event_listener::evm::contracts::EvmContractEventListener, job,
     Error,
 };
-use serde::{Deserialize, Serialize};
-use std::ops::Deref;
-use tokio::time::{sleep, Duration};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
-/// Stored packet information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct StoredPacket {
-    packet: Packet,
-    options: Bytes,
-    timestamp: u64,
-}
+/// Retries of a job hitting temporary errors, which go on until it succeeds or fails for good
+const RETRIES: RetryPolicy = RetryPolicy {
+    max_attempts: None,
+    delay: Duration::from_secs(5),
+    max_delay: Duration::from_secs(300),
+};
 
 #[derive(Debug, Clone, KeystoreContext, TangleClientContext, EVMProviderContext)]
 pub struct DvnContext {
@@ -34,19 +50,53 @@ pub struct DvnContext {
     pub config: StdGadgetConfiguration,
     #[call_id]
     pub call_id: Option<u64>,
-    pub store: LocalDatabase<StoredPacket>,
-    pub required_confirmations: u64,
-    pub receive_lib: Address,
+    /// Packets seen on the source chains, keyed by message ID
+    pub store: Arc<dyn PacketStore>,
+    /// Progress of every packet through the verification pipeline
+    pub jobs: JobQueue,
+    /// Last block of every source chain seen by the live event handlers
+    pub checkpoints: Checkpoints,
+    /// Last block of every source chain up to which backfilled events were fully processed
+    pub backfill_cursors: Checkpoints,
+    /// Chains this DVN bridges between, keyed by endpoint ID
+    pub chains: ChainRegistry,
+    /// Endpoint ID of the chain this job instance listens on
+    pub source_eid: u32,
     pub price_feed: Address,
     pub default_multiplier_bps: u16,
     // Single security verification configuration
     pub security_type: SecurityType,
 }
 
+impl DvnContext {
+    /// Context keeping its stores in `data_dir`. Its `source_eid` must be set before it is handed
+    /// to a job.
+    pub async fn new(
+        config: StdGadgetConfiguration,
+        dvn_config: &DvnConfig,
+        data_dir: &Path,
+    ) -> Result<Self, Error> {
+        let store = store::open(dvn_config.storage, data_dir)?;
+        Ok(Self {
+            config,
+            call_id: None,
+            jobs: JobQueue::new(store.clone()),
+            store,
+            checkpoints: Checkpoints::open(data_dir),
+            backfill_cursors: Checkpoints::open_backfill_cursors(data_dir),
+            chains: ChainRegistry::new(dvn_config.chains.clone()).await?,
+            source_eid: 0,
+            price_feed: Default::default(),
+            default_multiplier_bps: 10_000,
+            security_type: dvn_config.security_type.clone(),
+        })
+    }
+}
+
 // First job: Listen for and store packets
 #[job(
     id = 0,
-    params(packet, options),
+    params(packet, options, log),
     event_listener(
         listener = EvmContractEventListener<PacketSent>
         instance = ILayerZeroEndpointV2,
@@ -54,24 +104,40 @@ pub struct DvnContext {
         pre_processor = convert_packet_event,
     )
 )]
-pub async fn store_packet(packet: Packet, options: Bytes, ctx: DvnContext) -> Result<(), Error> {
-    let stored_packet = StoredPacket {
-        packet,
-        options,
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs(),
-    };
+pub async fn store_packet(
+    packet: Packet,
+    options: Bytes,
+    log: gadget_sdk::alloy_rpc_types::Log,
+    ctx: DvnContext,
+) -> Result<(), Error> {
+    let stored_packet = StoredPacket::new(packet, options, &log)?;
+    let tx_hash = stored_packet.transaction_hash;
+    let (message_id, record, inserted) = save_packet(stored_packet, &ctx).await?;
 
-    // Store using message_id as key
-    let message_id = calculate_message_id(&stored_packet.packet, &stored_packet.options)?;
-    ctx.store
-        .insert(&message_id.to_vec(), &stored_packet)
-        .await?;
+    // Resume an assignment whose DVNFeePaid event was processed before this packet
+    if inserted && record.state == JobState::Assigned {
+        tracing::info!(
+            "Packet {} arrived, resuming its parked assignment",
+            message_id
+        );
+        spawn_parked_verification(message_id, tx_hash, ctx);
+    }
 
     Ok(())
 }
 
+/// Store a packet under its message ID, returning the ID, its job and whether it was newly
+/// inserted
+async fn save_packet(
+    stored_packet: StoredPacket,
+    ctx: &DvnContext,
+) -> Result<(B256, JobRecord, bool), Error> {
+    // Store using message_id as key
+    let message_id = codec::packet_message_id(&stored_packet.packet);
+    let (record, inserted) = ctx.jobs.store_packet(message_id, &stored_packet).await?;
+    Ok((message_id, record, inserted))
+}
+
 // Second job: Process packets when selected as DVN
 #[job(
     id = 1,
@@ -88,75 +154,595 @@ pub async fn process_packet(
     log: gadget_sdk::alloy_rpc_types::Log,
     ctx: DvnContext,
 ) -> Result<bool, Error> {
-    // 1. Check if we're one of the selected DVNs
-    let our_address = ctx.config.address;
-    let is_required = fee_paid
+    Ok(handle_fee_paid(fee_paid, log, ctx).await?)
+}
+
+/// Accept the assignment announced by a `DVNFeePaid` event if it selected one of our DVNs, and
+/// verify its packet
+pub(crate) async fn handle_fee_paid(
+    fee_paid: DVNFeePaid,
+    log: gadget_sdk::alloy_rpc_types::Log,
+    ctx: DvnContext,
+) -> Result<bool, JobError> {
+    match accept_fee_paid(&fee_paid, &log, &ctx).await? {
+        Some((message_id, assignment)) => verify_assignment(message_id, assignment, &ctx).await,
+        None => Ok(false),
+    }
+}
+
+/// Persist the assignment announced by a `DVNFeePaid` event if it selected one of our DVNs,
+/// returning its message ID and where its job stands
+pub(crate) async fn accept_fee_paid(
+    fee_paid: &DVNFeePaid,
+    log: &gadget_sdk::alloy_rpc_types::Log,
+    ctx: &DvnContext,
+) -> Result<Option<(B256, Assignment)>, JobError> {
+    // 1. Check if one of our DVN contracts is among the selected DVNs
+    let block_number = log
+        .block_number
+        .ok_or_else(|| JobError::Fatal(Error::Client("DVNFeePaid log is still pending".into())))?;
+    let source_chain = ctx.chains.get(ctx.source_eid).map_err(JobError::Fatal)?;
+    let Some(selection) = dvn_selection(fee_paid, &source_chain.config) else {
+        ctx.checkpoints
+            .advance(ctx.source_eid, block_number)
+            .await?;
+        return Ok(None);
+    };
+
+    // 2. Recover the assignJob parameters from the transaction that emitted this event
+    let tx_hash = log.transaction_hash.ok_or_else(|| {
+        JobError::Fatal(Error::Client("Log is missing its transaction hash".into()))
+    })?;
+    let logs = assignment_logs(source_chain, tx_hash).await?;
+    let Some(packet) = packet_of_send(&logs, log, source_chain.config.endpoint) else {
+        return Err(JobError::Fatal(Error::Client(format!(
+            "No valid packet follows DVNFeePaid in transaction {}",
+            tx_hash
+        ))));
+    };
+    let message_id = codec::packet_message_id(&packet);
+    let Some((params, assignment_source)) =
+        recover_assignment(source_chain, tx_hash, &logs, message_id).await?
+    else {
+        return Err(JobError::Fatal(Error::Client(format!(
+            "No assignment for packet {} in transaction {}",
+            message_id, tx_hash
+        ))));
+    };
+    tracing::debug!(
+        "Recovered assignment in {} from {:?}",
+        tx_hash,
+        assignment_source
+    );
+
+    let assignment =
+        accept_assignment(&params, Some(selection), tx_hash, block_number, ctx).await?;
+    Ok(Some((message_id, assignment)))
+}
+
+// Third job: Process assignments announced by our own DVN contract
+#[job(
+    id = 2,
+    params(job_assigned, log),
+    event_listener(
+        listener = EvmContractEventListener<JobAssigned>
+        instance = LayerZeroDVNInstance,
+        abi = LAYER_ZERO_DVN_INSTANCE_ABI_STRING,
+        pre_processor = convert_job_assigned_event,
+    )
+)]
+pub async fn process_job_assigned(
+    job_assigned: JobAssigned,
+    log: gadget_sdk::alloy_rpc_types::Log,
+    ctx: DvnContext,
+) -> Result<bool, Error> {
+    // 1. Make sure the event was emitted by one of our DVN contracts on the source chain
+    let block_number = log
+        .block_number
+        .ok_or_else(|| Error::Client("JobAssigned log is still pending".into()))?;
+    let tx_hash = log
+        .transaction_hash
+        .ok_or_else(|| Error::Client("Log is missing its transaction hash".into()))?;
+    let source_chain = ctx.chains.get(ctx.source_eid)?;
+    if !source_chain.config.dvns().any(|dvn| *dvn == log.address()) {
+        return Ok(false);
+    }
+
+    // 2. Rebuild the AssignJobParam from the PacketSent log of the same transaction
+    let logs = receipt_logs(source_chain, tx_hash).await?;
+    let params = assignment_for_job(&job_assigned, &log, &logs, source_chain.config.endpoint)
+        .ok_or_else(|| {
+            Error::Client(format!(
+                "No packet in transaction {} matches its JobAssigned event",
+                tx_hash
+            ))
+        })?;
+
+    // 3. The DVNFeePaid log of the same transaction tells whether we are a required or optional DVN
+    let selection = logs
+        .iter()
+        .filter(|log| log.address() == source_chain.config.send_uln)
+        .filter_map(|log| log.log_decode::<DVNFeePaid>().ok())
+        .find_map(|fee_paid| dvn_selection(&fee_paid.inner.data, &source_chain.config));
+
+    Ok(process_assignment(params, selection, tx_hash, block_number, ctx).await?)
+}
+
+/// Persist an assignment recovered from `tx_hash` on the source chain and verify its packet,
+/// parking the job if the packet was not stored yet
+async fn process_assignment(
+    params: AssignJobParam,
+    selection: Option<(DvnSelection, u8)>,
+    tx_hash: B256,
+    block_number: u64,
+    ctx: DvnContext,
+) -> Result<bool, JobError> {
+    let message_id = codec::message_id(&params.packetHeader, params.payloadHash);
+    let assignment = accept_assignment(&params, selection, tx_hash, block_number, &ctx).await?;
+    verify_assignment(message_id, assignment, &ctx).await
+}
+
+/// Verify the packet of a newly started job, returning whether it is verified
+async fn verify_assignment(
+    message_id: B256,
+    assignment: Assignment,
+    ctx: &DvnContext,
+) -> Result<bool, JobError> {
+    match assignment {
+        // Temporary errors are retried within, so any error left means the job failed
+        Assignment::Started => run_verification(message_id, ctx)
+            .await
+            .map_err(JobError::Fatal),
+        Assignment::Parked => Ok(false),
+        Assignment::Existing(state) => Ok(state == JobState::Finalized),
+    }
+}
+
+/// Cross-check an assignment recovered from `tx_hash` on the source chain against its packet and
+/// persist it, so its job can be driven by [`advance_job`]
+pub(crate) async fn accept_assignment(
+    params: &AssignJobParam,
+    selection: Option<(DvnSelection, u8)>,
+    tx_hash: B256,
+    block_number: u64,
+    ctx: &DvnContext,
+) -> Result<Assignment, JobError> {
+    // 1. Derive the message ID from the assigned packet header and payload hash
+    let message_id = codec::message_id(&params.packetHeader, params.payloadHash);
+
+    match selection {
+        Some((selection, index)) => tracing::info!(
+            "Selected as {:?} DVN {} for packet {}",
+            selection,
+            index,
+            message_id
+        ),
+        None => tracing::info!("Assigned packet {}", message_id),
+    }
+
+    // 2. Cross-check every assigned field against the PacketSent logs of the same transaction and
+    // our configuration, refusing jobs whose confirmations are outside the source chain's bounds
+    let source_chain = ctx.chains.get(ctx.source_eid).map_err(JobError::Fatal)?;
+    let logs = assignment_logs(source_chain, tx_hash).await?;
+    verify_packet_params(message_id, params, &logs, ctx)?;
+
+    // 3. Store the packets of the transaction, in case this event was processed before PacketSent
+    store_sent_packets(&logs, ctx).await?;
+
+    // 4. Persist the assignment so the job can be resumed after a restart. The same assignment
+    // is reported by both DVNFeePaid and JobAssigned, so only the first one starts the job. If the
+    // packet is still missing, the assignment is parked until store_packet sees it
+    let (record, assignment) = ctx
+        .jobs
+        .assign(message_id, params.confirmations, selection)
+        .await?;
+    ctx.checkpoints
+        .advance(ctx.source_eid, block_number)
+        .await?;
+    match assignment {
+        Assignment::Existing(_) => {
+            tracing::debug!("Job {} is already {:?}", message_id, record.state)
+        }
+        Assignment::Parked => tracing::info!(
+            "Packet {} not found yet, parking its assignment",
+            message_id
+        ),
+        Assignment::Started => {}
+    }
+
+    Ok(assignment)
+}
+
+/// Whether one of our DVN contracts on the source chain was selected as a required or optional
+/// DVN for the packet, along with its index among the required DVNs followed by the optional DVNs
+pub(crate) fn dvn_selection(
+    fee_paid: &DVNFeePaid,
+    source: &ChainConfig,
+) -> Option<(DvnSelection, u8)> {
+    let dvns = source.dvns().collect::<Vec<_>>();
+    let required = fee_paid
         .requiredDVNs
         .iter()
-        .any(|addr| *addr == our_address);
-    let is_optional = fee_paid
+        .map(|dvn| (DvnSelection::Required, dvn));
+    let optional = fee_paid
         .optionalDVNs
         .iter()
-        .any(|addr| *addr == our_address);
+        .map(|dvn| (DvnSelection::Optional, dvn));
 
-    if !is_required && !is_optional {
-        return Ok(false);
+    required
+        .chain(optional)
+        .enumerate()
+        .find(|(_, (_, dvn))| dvns.contains(dvn))
+        .map(|(index, (selection, _))| (selection, index as u8))
+}
+
+/// Logs of `tx_hash` on the source chain, which made an assignment and so must have been mined
+async fn assignment_logs(
+    source: &Chain,
+    tx_hash: B256,
+) -> Result<Vec<gadget_sdk::alloy_rpc_types::Log>, Error> {
+    let logs = receipt_logs(source, tx_hash).await?;
+    if logs.is_empty() {
+        // A lagging node may not have the receipt yet
+        return Err(Error::Client(format!("Receipt for {} not found", tx_hash)));
     }
 
-    // 2. Get the transaction that emitted this event
-    let tx = ctx
-        .evm_provider()
-        .await?
-        .get_transaction(log.transaction_hash)
-        .await?
-        .ok_or_else(|| Error::Client("Transaction not found".into()))?;
+    Ok(logs)
+}
 
-    // 3. Decode the transaction input to get the AssignJob call data
-    let assign_job = AssignJobCall::abi_decode(&tx.input, true)
-        .map_err(|e| Error::Client(format!("Failed to decode transaction input: {}", e)))?;
+/// Store every packet sent through the source chain endpoint in `logs`. Invalid packets are
+/// skipped, as retrying cannot fix them.
+async fn store_sent_packets(
+    logs: &[gadget_sdk::alloy_rpc_types::Log],
+    ctx: &DvnContext,
+) -> Result<(), Error> {
+    let source = ctx.chains.get(ctx.source_eid)?;
+    for log in logs {
+        if log.address() != source.config.endpoint {
+            continue;
+        }
+        let Ok(packet_sent) = log.log_decode::<PacketSent>() else {
+            continue;
+        };
 
-    // 4. Extract packet parameters from the assign job call
-    let message_id = calculate_message_id_from_params(&assign_job.param)?;
+        let (packet, options, log) =
+            match convert_packet_event((packet_sent.inner.data, log.clone())).await {
+                Ok(packet) => packet,
+                Err(e) => {
+                    tracing::warn!(
+                        "Skipping invalid packet in transaction {:?}: {}",
+                        log.transaction_hash,
+                        e
+                    );
+                    continue;
+                }
+            };
+        save_packet(StoredPacket::new(packet, options, &log)?, ctx).await?;
+    }
 
-    // 5. Verify this matches the stored packet
-    let stored_packet: StoredPacket = ctx
-        .store
-        .get(&message_id.to_vec())
-        .await?
-        .ok_or_else(|| Error::Client("Packet not found".into()))?;
+    Ok(())
+}
+
+/// Recover the assignment for the parked job `message_id` from `tx_hash`, the transaction that
+/// emitted its packet, and cross-check it like a newly accepted assignment
+async fn check_parked_assignment(
+    message_id: B256,
+    tx_hash: B256,
+    ctx: &DvnContext,
+) -> Result<(), JobError> {
+    let source_chain = ctx.chains.get(ctx.source_eid).map_err(JobError::Fatal)?;
+    let logs = assignment_logs(source_chain, tx_hash).await?;
+    let Some((params, _)) = recover_assignment(source_chain, tx_hash, &logs, message_id).await?
+    else {
+        return Err(JobError::Fatal(Error::Client(format!(
+            "No assignment for packet {} in transaction {}",
+            message_id, tx_hash
+        ))));
+    };
 
-    // 6. Verify the parameters match
-    verify_packet_params(&stored_packet, &assign_job.param)?;
+    verify_packet_params(message_id, &params, &logs, ctx)
+}
 
-    // 7. Check if already verified
-    if is_already_verified(&message_id, &ctx).await? {
-        return Ok(true);
+/// Why a step of a job failed
+#[derive(Debug)]
+pub(crate) enum JobError {
+    /// A temporary failure, such as an RPC error, after which the job is retried from its
+    /// persisted state
+    Retryable(Error),
+    /// The job cannot succeed
+    Fatal(Error),
+}
+
+impl From<Error> for JobError {
+    fn from(e: Error) -> Self {
+        Self::Retryable(e)
+    }
+}
+
+impl From<SubmitError> for JobError {
+    fn from(e: SubmitError) -> Self {
+        match e {
+            SubmitError::Transport(_) => Self::Retryable(e.into()),
+            _ => Self::Fatal(e.into()),
+        }
     }
+}
 
-    // 8. Wait for required confirmations
-    wait_for_confirmations(stored_packet.packet.dstEid, ctx.required_confirmations).await?;
+impl From<ProverError> for JobError {
+    fn from(e: ProverError) -> Self {
+        match e {
+            ProverError::Invalid(_) => Self::Fatal(e.into()),
+            _ => Self::Retryable(e.into()),
+        }
+    }
+}
 
-    // 9. Perform security verification
-    verify_security(&stored_packet.packet, &stored_packet.options, &ctx).await?;
+impl From<JobError> for Error {
+    fn from(e: JobError) -> Self {
+        match e {
+            JobError::Retryable(e) | JobError::Fatal(e) => e,
+        }
+    }
+}
 
-    // 10. Call contract to verify on ULN
-    let verification_result = verify_on_destination(
-        &stored_packet.packet,
-        &stored_packet.options,
-        ctx.receive_lib,
-    )
-    .await?;
+/// Drive the job for `message_id` from its persisted state until it is finalized or fails.
+///
+/// Every step is recorded in [`DvnContext::jobs`] before moving on, so a job interrupted by a
+/// restart picks up where it left off when this is called again. Temporary errors are retried
+/// with backoff and leave the job in its current state. A job already driven by another task is
+/// left to it.
+pub async fn run_verification(message_id: B256, ctx: &DvnContext) -> Result<bool, Error> {
+    // The same job may be resumed on startup and started by a new event, but only one task may
+    // step it, or it could be submitted twice
+    let Some(_running) = ctx.jobs.try_run(message_id) else {
+        tracing::debug!("Job {} is already being verified", message_id);
+        let record = ctx.jobs.get(message_id).await?;
+        return Ok(record.is_some_and(|record| record.state == JobState::Finalized));
+    };
+
+    with_job_retries(message_id, ctx, || advance_job(message_id, ctx)).await
+}
+
+/// Run `step` for the job for `message_id` until it succeeds, retrying temporary errors with
+/// backoff and failing the job on any other error
+async fn with_job_retries<T, F, Fut>(
+    message_id: B256,
+    ctx: &DvnContext,
+    step: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, JobError>>,
+{
+    let result = with_retries(&format!("Job {}", message_id), &RETRIES, Propagate, step).await;
+    if let Err(e) = &result {
+        ctx.jobs.fail(message_id, e.to_string()).await?;
+    }
+    result
+}
+
+/// Run the job for `message_id` in the background, logging its failure
+pub fn spawn_verification(message_id: B256, ctx: DvnContext) {
+    tokio::spawn(async move {
+        if let Err(e) = run_verification(message_id, &ctx).await {
+            tracing::error!("Failed to verify packet {}: {}", message_id, e);
+        }
+    });
+}
+
+/// Cross-check the parked job for `message_id` against the transaction `tx_hash` that emitted its
+/// packet, then run it in the background, logging its failure
+fn spawn_parked_verification(message_id: B256, tx_hash: B256, ctx: DvnContext) {
+    tokio::spawn(async move {
+        let result = async {
+            with_job_retries(message_id, &ctx, || {
+                check_parked_assignment(message_id, tx_hash, &ctx)
+            })
+            .await?;
+            run_verification(message_id, &ctx).await
+        };
+        if let Err(e) = result.await {
+            tracing::error!("Failed to verify packet {}: {}", message_id, e);
+        }
+    });
+}
+
+/// Step the job for `message_id` until it has nothing left to do
+async fn advance_job(message_id: B256, ctx: &DvnContext) -> Result<bool, JobError> {
+    loop {
+        if let Some(verified) = advance_job_step(message_id, ctx).await? {
+            return Ok(verified);
+        }
+    }
+}
+
+/// Run the step for the current state of the job for `message_id` and record the state it moved
+/// to. Returns whether the packet is verified once the job has nothing left to do.
+pub(crate) async fn advance_job_step(
+    message_id: B256,
+    ctx: &DvnContext,
+) -> Result<Option<bool>, JobError> {
+    let Some(mut stored_packet) = ctx.store.get(message_id).await? else {
+        // Parked until store_packet sees the packet
+        tracing::debug!(
+            "Packet {} not found yet, leaving its job parked",
+            message_id
+        );
+        return Ok(Some(false));
+    };
+    let source_chain = ctx
+        .chains
+        .get(stored_packet.packet.srcEid)
+        .map_err(JobError::Fatal)?;
+    let destination_chain = ctx
+        .chains
+        .get(stored_packet.packet.dstEid)
+        .map_err(JobError::Fatal)?;
+    let submitter = operator_submitter(ctx).map_err(JobError::Fatal)?;
+
+    let mut record = ctx
+        .jobs
+        .get(message_id)
+        .await?
+        .ok_or_else(|| JobError::Fatal(Error::Client("Job not found".into())))?;
+    let required_confirmations = record.required_confirmations.ok_or_else(|| {
+        JobError::Fatal(Error::Client("Job has no required confirmations".into()))
+    })?;
+
+    match record.state {
+        JobState::Assigned => {
+            // Check if already verified
+            let next = if is_already_verified(
+                message_id,
+                &stored_packet.packet,
+                required_confirmations,
+                ctx,
+            )
+            .await?
+            {
+                tracing::info!("Packet {} already verified, skipping", message_id);
+                JobState::Finalized
+            } else {
+                JobState::Confirming
+            };
+            ctx.jobs.transition(message_id, next).await?;
+        }
+        JobState::Confirming => {
+            // Wait for required confirmations on the source chain
+            stored_packet = wait_for_canonical_confirmations(
+                message_id,
+                stored_packet,
+                source_chain,
+                required_confirmations,
+                ctx,
+            )
+            .await?;
+
+            // Perform security verification
+            verify_security(
+                &stored_packet,
+                record.dvn_index,
+                &destination_chain.config,
+                ctx,
+            )
+            .await?;
+            ctx.jobs
+                .transition(message_id, JobState::SecurityChecked)
+                .await?;
+        }
+        JobState::SecurityChecked => {
+            // Make sure the packet was not reorged out while we were verifying it
+            match packet_inclusion(
+                source_chain,
+                stored_packet.block_number,
+                stored_packet.block_hash,
+                stored_packet.transaction_hash,
+                required_confirmations,
+            )
+            .await?
+            {
+                PacketInclusion::Canonical => {}
+                // Back on the canonical chain in another block, which must be confirmed and
+                // checked again
+                PacketInclusion::Reincluded {
+                    block_number,
+                    block_hash,
+                } => {
+                    tracing::warn!(
+                        "Packet {} moved from block {} to {} before submission",
+                        message_id,
+                        stored_packet.block_number,
+                        block_number
+                    );
+                    stored_packet.block_number = block_number;
+                    stored_packet.block_hash = block_hash;
+                    ctx.store.insert(message_id, &stored_packet).await?;
+                    ctx.jobs
+                        .transition(message_id, JobState::Confirming)
+                        .await?;
+                    return Ok(None);
+                }
+                PacketInclusion::Orphaned => return Err(drop_orphaned(message_id, ctx).await),
+            }
 
-    Ok(verification_result)
+            // Call contract to verify on ULN
+            let message = codec::encode_adapter_message(
+                destination_chain.config.receive_uln,
+                &stored_packet.packet,
+            );
+            match submitter.send(destination_chain, message_id, message).await {
+                Ok(tx_hash) => {
+                    record.state = JobState::Submitted;
+                    record.transaction_hash = Some(tx_hash);
+                    ctx.jobs.update(record).await?;
+                }
+                Err(SubmitError::MessageAlreadyVerified) => {
+                    tracing::info!("Packet {} already verified", message_id);
+                    ctx.jobs.transition(message_id, JobState::Finalized).await?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        JobState::Submitted => {
+            let tx_hash = record.transaction_hash.ok_or_else(|| {
+                JobError::Fatal(Error::Client("Submitted job has no transaction".into()))
+            })?;
+            let next = match submitter.wait_for_receipt(destination_chain, tx_hash).await {
+                Ok(()) => {
+                    tracing::info!("Verified packet {} in transaction {}", message_id, tx_hash);
+                    JobState::Finalized
+                }
+                // The transaction may have been dropped or replaced, so resend it unless the
+                // packet got verified in the meantime or the transaction can still land
+                Err(e) => {
+                    if is_already_verified(
+                        message_id,
+                        &stored_packet.packet,
+                        required_confirmations,
+                        ctx,
+                    )
+                    .await?
+                    {
+                        JobState::Finalized
+                    } else if matches!(e, SubmitError::Transport(_))
+                        && submitter.is_pending(destination_chain, tx_hash).await?
+                    {
+                        // Resending now could verify the packet twice, so keep waiting
+                        return Err(JobError::Retryable(e.into()));
+                    } else {
+                        tracing::warn!(
+                            "Verification {} for packet {} did not land: {}",
+                            tx_hash,
+                            message_id,
+                            e
+                        );
+                        JobState::SecurityChecked
+                    }
+                }
+            };
+            ctx.jobs.transition(message_id, next).await?;
+        }
+        JobState::Finalized => return Ok(Some(true)),
+        JobState::Failed => return Ok(Some(false)),
+        JobState::Stored => {
+            return Err(JobError::Fatal(Error::Client(
+                "Job was never assigned".into(),
+            )))
+        }
+    }
+
+    Ok(None)
 }
 
-async fn convert_packet_event(
+pub(crate) async fn convert_packet_event(
     event: (PacketSent, gadget_sdk::alloy_rpc_types::Log),
-) -> Result<(Packet, Bytes), Error> {
-    let packet_sent = event.0;
-    let packet = Packet::abi_decode(&packet_sent.encodedPayload.to_vec()[..], true)
-        .map_err(|e| Error::Client(format!("Failed to decode packet: {}", e)))?;
-    Ok((packet, packet_sent.options))
+) -> Result<(Packet, Bytes, gadget_sdk::alloy_rpc_types::Log), Error> {
+    let (packet_sent, log) = event;
+    let packet = codec::decode_packet(&packet_sent.encodedPayload)?;
+
+    // Reject malformed or spoofed packets before we ever sign anything for them
+    codec::validate_guid(&packet)?;
+    Ok((packet, packet_sent.options, log))
 }
 
 async fn convert_fee_event(
@@ -165,45 +751,178 @@ async fn convert_fee_event(
     Ok(event)
 }
 
-async fn is_already_verified(message_id: &[u8; 32], ctx: &DvnContext) -> Result<bool, Error> {
-    // TODO: Call DVN contract's verifiedMessages mapping
-    Ok(false)
+async fn convert_job_assigned_event(
+    event: (JobAssigned, gadget_sdk::alloy_rpc_types::Log),
+) -> Result<(JobAssigned, gadget_sdk::alloy_rpc_types::Log), Error> {
+    Ok(event)
 }
 
-async fn wait_for_confirmations(dst_eid: u32, required_confirmations: u64) -> Result<(), Error> {
-    let mut current_attempt = 0;
-    let max_attempts = 10;
-    let initial_delay = Duration::from_secs(1);
-
+/// Wait for the packet's confirmations, following it to its new block if the `PacketSent`
+/// transaction was reorged into a different block and dropping it if it was orphaned
+async fn wait_for_canonical_confirmations(
+    message_id: B256,
+    mut stored_packet: StoredPacket,
+    source: &Chain,
+    required_confirmations: u64,
+    ctx: &DvnContext,
+) -> Result<StoredPacket, JobError> {
     loop {
-        // Get current block number
-        let current_block = get_current_block(dst_eid).await?;
+        wait_for_confirmations(source, stored_packet.block_number, required_confirmations).await?;
 
-        // Check if we have enough confirmations
-        if current_block.confirmations >= required_confirmations {
-            return Ok(());
+        match packet_inclusion(
+            source,
+            stored_packet.block_number,
+            stored_packet.block_hash,
+            stored_packet.transaction_hash,
+            required_confirmations,
+        )
+        .await?
+        {
+            PacketInclusion::Canonical => return Ok(stored_packet),
+            PacketInclusion::Reincluded {
+                block_number,
+                block_hash,
+            } => {
+                tracing::warn!(
+                    "Packet {} moved from block {} to {} after a reorg",
+                    message_id,
+                    stored_packet.block_number,
+                    block_number
+                );
+                stored_packet.block_number = block_number;
+                stored_packet.block_hash = block_hash;
+                ctx.store.insert(message_id, &stored_packet).await?;
+            }
+            PacketInclusion::Orphaned => return Err(drop_orphaned(message_id, ctx).await),
         }
+    }
+}
 
-        current_attempt += 1;
-        if current_attempt >= max_attempts {
-            return Err(Error::Client("Max confirmation attempts exceeded".into()));
-        }
+/// Drop a packet orphaned by a reorg, whose job can no longer succeed
+async fn drop_orphaned(message_id: B256, ctx: &DvnContext) -> JobError {
+    tracing::warn!("Packet {} was orphaned by a reorg, dropping it", message_id);
+    if let Err(e) = ctx.store.remove(message_id).await {
+        return e.into();
+    }
 
-        // Exponential backoff
-        let delay = initial_delay * 2u32.pow(current_attempt as u32);
-        sleep(delay).await;
+    JobError::Fatal(Error::Client(format!(
+        "Packet {} is no longer on the source chain",
+        message_id
+    )))
+}
+
+/// Check whether the packet was already verified on the destination chain, either through our
+/// DVN instance or directly on the receive ULN
+async fn is_already_verified(
+    message_id: B256,
+    packet: &Packet,
+    required_confirmations: u64,
+    ctx: &DvnContext,
+) -> Result<bool, Error> {
+    let destination = ctx.chains.get(packet.dstEid)?;
+
+    // 1. Check the DVN instance's verifiedMessages mapping
+    let dvn = LayerZeroDVNInstance::new(destination.config.dvn, destination.provider.clone());
+    let verified = dvn
+        .verifiedMessages(message_id)
+        .call()
+        .await
+        .map_err(|e| Error::Client(format!("Failed to query verifiedMessages: {}", e)))?
+        ._0;
+    if verified {
+        return Ok(true);
     }
+
+    // 2. Check whether our DVN already submitted enough confirmations to the receive ULN
+    let receive_uln =
+        ReceiveUln302::new(destination.config.receive_uln, destination.provider.clone());
+    let header_hash = keccak256(PacketHeader::from(packet).encode());
+    let verification = receive_uln
+        .hashLookup(
+            header_hash,
+            codec::payload_hash(packet),
+            destination.config.dvn,
+        )
+        .call()
+        .await
+        .map_err(|e| Error::Client(format!("Failed to query ULN hashLookup: {}", e)))?;
+
+    Ok(verification.submitted && verification.confirmations >= required_confirmations)
 }
 
-async fn verify_security(packet: &Packet, options: &Bytes, ctx: &DvnContext) -> Result<(), Error> {
+/// Check the packet against the configured security type. A failed check fails the job, except
+/// while the prover has no proof of the packet, or none that verifies for it.
+async fn verify_security(
+    stored_packet: &StoredPacket,
+    dvn_index: Option<u8>,
+    destination: &ChainConfig,
+    ctx: &DvnContext,
+) -> Result<(), JobError> {
+    let packet = &stored_packet.packet;
+
+    // Only the options addressed to our DVN are handed to the verifier; without a known index
+    // none of them can be attributed to us
+    let options = PacketOptions::decode(&stored_packet.options).map_err(JobError::Fatal)?;
     let verification_context = VerificationContext {
-        chain_id: ctx.config.chain_id,
-        verifier_address: ctx.receive_lib,
-        extra_data: options.clone(),
+        chain_id: destination.chain_id,
+        verifier_address: destination.receive_uln,
+        message_id: codec::packet_message_id(packet),
+        header: PacketHeader::from(packet).encode(),
+        payload_hash: codec::payload_hash(packet),
+        source_block_hash: stored_packet.block_hash,
+        extra_data: dvn_index
+            .map(|index| options.encode_dvn_options(index))
+            .unwrap_or_default(),
     };
 
-    let data = encode_verification_data(packet)?;
+    // A ZK proof the DVN options do not carry is fetched from the prover, if there is one
+    if let SecurityType::ZkProof {
+        verification_key,
+        proof_system,
+        prover_url: Some(prover_url),
+        public_inputs,
+    } = &ctx.security_type
+    {
+        if !ZkProofVerifier::has_option_proof(&verification_context).map_err(JobError::Fatal)? {
+            let verifier = ZkProofVerifier::new(
+                verification_key.clone(),
+                proof_system.clone(),
+                public_inputs.clone(),
+            );
+            let receipt = ProverClient::new(prover_url)
+                .receipt(verification_context.message_id)
+                .await?;
+            // After a reorg the prover may still serve its proof about the block the packet was
+            // reorged out of, so a proof that does not match the packet is fetched again. Only a
+            // malformed one fails the job.
+            if !verifier
+                .verify_receipt(&receipt, &verification_context)
+                .map_err(JobError::Fatal)?
+            {
+                return Err(JobError::Retryable(Error::Client(format!(
+                    "Proof from the prover does not verify for packet {} in block {}",
+                    verification_context.message_id, verification_context.source_block_hash
+                ))));
+            }
+            return Ok(());
+        }
+    }
+
+    verify_security_type(
+        &codec::encode_verification_data(packet),
+        &verification_context,
+        ctx,
+    )
+    .await
+    .map_err(JobError::Fatal)
+}
 
+/// Check the packet's verification data against the configured security type
+async fn verify_security_type(
+    data: &[u8],
+    verification_context: &VerificationContext,
+    ctx: &DvnContext,
+) -> Result<(), Error> {
     // Create and use the appropriate verifier based on security type
     let verified = match &ctx.security_type {
         SecurityType::Signature {
@@ -212,31 +931,39 @@ async fn verify_security(packet: &Packet, options: &Bytes, ctx: &DvnContext) ->
         } => {
             let verifier =
                 crate::security::SignatureVerifier::new(required_signers.clone(), *threshold);
-            verifier.verify(&data, &verification_context).await?
+            verifier.verify(data, verification_context).await?
         }
         SecurityType::ZkProof {
             verification_key,
             proof_system,
+            public_inputs,
+            ..
         } => {
-            let verifier = crate::security::ZkProofVerifier::new(
+            let verifier = ZkProofVerifier::new(
                 verification_key.clone(),
                 proof_system.clone(),
+                public_inputs.clone(),
             );
-            verifier.verify(&data, &verification_context).await?
+            verifier.verify(data, verification_context).await?
         }
         SecurityType::Oracle {
             providers,
             threshold,
         } => {
             let verifier = crate::security::OracleVerifier::new(providers.clone(), *threshold);
-            verifier.verify(&data, &verification_context).await?
+            verifier.verify(data, verification_context).await?
         }
         SecurityType::Mpc {
             participants,
             threshold,
+            public_keys,
         } => {
-            let verifier = crate::security::MpcVerifier::new(participants.clone(), *threshold);
-            verifier.verify(&data, &verification_context).await?
+            let verifier = crate::security::MpcVerifier::new(
+                participants.clone(),
+                *threshold,
+                public_keys.clone(),
+            );
+            verifier.verify(data, verification_context).await?
         }
     };
 
@@ -247,94 +974,54 @@ async fn verify_security(packet: &Packet, options: &Bytes, ctx: &DvnContext) ->
     Ok(())
 }
 
-async fn verify_on_destination(
-    packet: &Packet,
-    options: &Bytes,
-    receive_lib: Address,
-) -> Result<bool, Error> {
-    let message_id = calculate_message_id(packet, options)?;
-    let encoded_message = encode_verification_message(packet, receive_lib)?;
-
-    // Call the DVN contract's verifyMessageHash function
-    let result = call_verify_message_hash(message_id, encoded_message).await?;
-
-    Ok(result)
-}
-
-fn calculate_message_id(packet: &Packet, options: &Bytes) -> Result<[u8; 32], Error> {
-    // Encode packet header
-    let packet_header = encode_packet_header(packet)?;
-
-    // Calculate payload hash
-    let payload_hash = keccak256(&packet.message);
-
-    // Calculate message ID: keccak256(abi.encodePacked(packet_header, payload_hash))
-    let mut message_data = Vec::with_capacity(packet_header.len() + 32);
-    message_data.extend_from_slice(&packet_header);
-    message_data.extend_from_slice(&payload_hash);
-
-    Ok(keccak256(&message_data))
-}
-
-fn encode_packet_header(packet: &Packet) -> Result<Bytes, Error> {
-    // Encode according to LayerZero format:
-    // PACKET_VERSION (uint8)
-    // nonce (uint64)
-    // srcEid (uint32)
-    // sender (bytes32)
-    // dstEid (uint32)
-    // receiver (bytes32)
-    let mut data = Vec::with_capacity(1 + 8 + 4 + 32 + 4 + 32);
-
-    data.push(1u8); // PACKET_VERSION
-    data.extend_from_slice(&packet.nonce.to_be_bytes());
-    data.extend_from_slice(&packet.srcEid.to_be_bytes());
-    data.extend_from_slice(&packet.sender.to_fixed_bytes());
-    data.extend_from_slice(&packet.dstEid.to_be_bytes());
-    data.extend_from_slice(&packet.receiver);
+/// Submitter signing with the operator's ECDSA key
+fn operator_submitter(ctx: &DvnContext) -> Result<VerificationSubmitter, Error> {
+    let signer = ctx
+        .config
+        .first_ecdsa_signer()
+        .map_err(|e| Error::Client(format!("Failed to load ECDSA key: {}", e)))?
+        .alloy_key()
+        .map_err(|e| Error::Client(format!("Failed to load ECDSA key: {}", e)))?;
 
-    Ok(data.into())
+    Ok(VerificationSubmitter::new(signer))
 }
 
+/// Cross-check every field of `assign_params` against the `PacketSent` logs among `logs`, those of
+/// the transaction that made the assignment, and our configuration, reporting all mismatching
+/// fields at once
 fn verify_packet_params(
-    stored_packet: &StoredPacket,
+    message_id: B256,
     assign_params: &AssignJobParam,
-) -> Result<(), Error> {
-    // Verify that the stored packet matches the parameters from the assign job
-    if stored_packet.packet.dstEid != assign_params.dstEid {
-        return Err(Error::Client("Destination EID mismatch".into()));
-    }
+    logs: &[gadget_sdk::alloy_rpc_types::Log],
+    ctx: &DvnContext,
+) -> Result<(), JobError> {
+    let source = &ctx
+        .chains
+        .get(ctx.source_eid)
+        .map_err(JobError::Fatal)?
+        .config;
+    let sent = sent_packets(logs, source.endpoint);
+    let destinations = ctx
+        .chains
+        .iter()
+        .map(|chain| chain.config.eid)
+        .filter(|eid| *eid != source.eid)
+        .collect::<Vec<_>>();
 
-    let calculated_hash = keccak256(&stored_packet.packet.message);
-    if calculated_hash != assign_params.payloadHash {
-        return Err(Error::Client("Payload hash mismatch".into()));
+    let report = check_assignment(
+        assign_params,
+        &sent,
+        source.eid,
+        &source.confirmations,
+        &destinations,
+    );
+    if !report.is_empty() {
+        tracing::warn!("Rejecting assignment for packet {}: {}", message_id, report);
+        return Err(JobError::Fatal(Error::Client(format!(
+            "Assignment for packet {} does not match: {}",
+            message_id, report
+        ))));
     }
 
     Ok(())
 }
-
-fn calculate_message_id_from_params(params: &AssignJobParam) -> Result<[u8; 32], Error> {
-    // We need to construct a packet header from the params and combine it with the payload hash
-    // Format matches encode_packet_header:
-    // PACKET_VERSION (uint8)
-    // nonce (uint64)
-    // srcEid (uint32)
-    // sender (bytes32)
-    // dstEid (uint32)
-    // receiver (bytes32)
-    let mut header_data = Vec::with_capacity(1 + 8 + 4 + 32 + 4 + 32);
-
-    header_data.push(1u8); // PACKET_VERSION
-    header_data.extend_from_slice(&params.nonce.to_be_bytes());
-    header_data.extend_from_slice(&params.srcEid.to_be_bytes());
-    header_data.extend_from_slice(&params.sender.to_fixed_bytes());
-    header_data.extend_from_slice(&params.dstEid.to_be_bytes());
-    header_data.extend_from_slice(&params.receiver);
-
-    // Combine header with payload hash
-    let mut message_data = Vec::with_capacity(header_data.len() + 32);
-    message_data.extend_from_slice(&header_data);
-    message_data.extend_from_slice(&params.payloadHash);
-
-    Ok(keccak256(&message_data))
-}
@@ -2,8 +2,22 @@ use alloy_sol_types::sol;
 use gadget_sdk::load_abi;
 use serde::{Deserialize, Serialize};
 
+pub mod assignment;
+pub mod backfill;
+pub mod chains;
+pub mod cli;
+pub mod codec;
+pub mod config;
+pub mod confirmations;
 mod job;
+pub mod manual;
+pub mod options;
+pub mod queue;
+pub mod retention;
+mod retry;
 mod security;
+pub mod store;
+pub mod submitter;
 
 pub use job::*;
 
@@ -26,11 +40,43 @@ sol!(
 sol!(
     #[allow(missing_docs)]
     #[sol(rpc)]
-    #[derive(Debug, Serialize, Deserialize)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     ISendLib,
     "contracts/out/ISendLib.sol/ISendLib.json"
 );
 
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    #[derive(Debug, Serialize, Deserialize)]
+    ILayerZeroDVN,
+    "contracts/out/ILayerZeroDVN.sol/ILayerZeroDVN.json"
+);
+
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    #[derive(Debug, Serialize, Deserialize)]
+    SendUln302,
+    "contracts/out/SendUln302.sol/SendUln302.json"
+);
+
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    #[derive(Debug, Serialize, Deserialize)]
+    ReceiveUln302,
+    "contracts/out/ReceiveUln302.sol/ReceiveUln302.json"
+);
+
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    #[derive(Debug, Serialize, Deserialize)]
+    LayerZeroDVNInstance,
+    "contracts/out/LayerZeroDVNInstance.sol/LayerZeroDVNInstance.json"
+);
+
 load_abi!(
     ILAYER_ZERO_ENDPOINT_V2_ABI_STRING,
     "contracts/out/ILayerZeroEndpointV2.sol/ILayerZeroEndpointV2.json"
@@ -40,3 +86,8 @@ load_abi!(
     ILAYER_ZERO_SEND_ULN_BASE_ABI_STRING
     "contracts/out/SendUln302.sol/SendUln302.json"
 );
+
+load_abi!(
+    LAYER_ZERO_DVN_INSTANCE_ABI_STRING,
+    "contracts/out/LayerZeroDVNInstance.sol/LayerZeroDVNInstance.json"
+);
@@ -1,14 +1,107 @@
 use color_eyre::Result;
 use gadget_sdk as sdk;
+use gadget_sdk::config::{ContextConfig, StdGadgetConfiguration};
+use gadget_sdk::prometheus::{self, PrometheusConfig, REGISTRY};
 use gadget_sdk::runners::BlueprintRunner;
+use layerzero_dvn_blueprint_template::{
+    backfill::backfill, cli::OperatorCommand, config::DvnConfig, retention::Retention,
+    run_verification, DvnContext, ILayerZeroEndpointV2, LayerZeroDVNInstance,
+    ProcessJobAssignedEventHandler, ProcessPacketEventHandler, SendUln302, StorePacketEventHandler,
+};
+use structopt::StructOpt;
 
-#[sdk::main(env)]
+#[tokio::main]
 async fn main() -> Result<()> {
-    let signer = env.first_sr25519_signer()?;
-    let client = env.client().await?;
+    sdk::logging::setup_log();
+
+    // Operator commands work on the local stores, anything else is the gadget's own CLI
+    if let Some(command) = OperatorCommand::from_args_if_present() {
+        command.execute().await?;
+        return Ok(());
+    }
+
+    let env = sdk::config::load(ContextConfig::from_args())?;
+    run(env).await
+}
+
+async fn run(env: StdGadgetConfiguration) -> Result<()> {
+    let dvn_config = DvnConfig::from_env()?;
+    let data_dir = env.data_dir.clone().unwrap_or_default();
+    let context = DvnContext::new(env.clone(), &dvn_config, &data_dir).await?;
+    let chains = context.chains.clone();
+
+    // Resume every verification that was in progress when the DVN last stopped
+    for record in context.jobs.pending().await? {
+        tracing::info!("Resuming job {} from {:?}", record.message_id, record.state);
+        let ctx = context.clone();
+        tokio::spawn(async move {
+            if let Err(e) = run_verification(record.message_id, &ctx).await {
+                tracing::error!("Failed to resume job {}: {}", record.message_id, e);
+            }
+        });
+    }
+
+    // Periodically discard, archive and evict stored packets
+    if dvn_config.retention.enabled {
+        let retention = Retention::new(
+            dvn_config.retention,
+            context.store.clone(),
+            context.jobs.clone(),
+            &data_dir,
+            &REGISTRY,
+        )?;
+        tokio::spawn(retention.run());
+    }
+
+    if let Some(bind_addr) = dvn_config.metrics_addr {
+        tokio::spawn(async move {
+            if let Err(e) = prometheus::setup(PrometheusConfig::Enabled { bind_addr }).await {
+                tracing::error!("Metrics endpoint failed: {}", e);
+            }
+        });
+    }
 
     tracing::info!("Starting the event watcher ...");
-    BlueprintRunner::new((), env).run().await?;
+    let mut runner = BlueprintRunner::new((), env);
+
+    // Listen for packets, fee payments and assignments on every configured source chain
+    for chain in chains.iter() {
+        let ctx = DvnContext {
+            source_eid: chain.config.eid,
+            ..context.clone()
+        };
+
+        // Catch up on events emitted while the DVN was offline
+        if dvn_config.backfill.enabled {
+            let ctx = ctx.clone();
+            let backfill_config = dvn_config.backfill;
+            tokio::spawn(async move {
+                if let Err(e) = backfill(backfill_config, &ctx).await {
+                    tracing::error!("Backfill of EID {} failed: {}", ctx.source_eid, e);
+                }
+            });
+        }
+
+        runner = runner
+            .job(StorePacketEventHandler {
+                contract: ILayerZeroEndpointV2::new(chain.config.endpoint, chain.provider.clone()),
+                context: ctx.clone(),
+            })
+            .job(ProcessPacketEventHandler {
+                contract: SendUln302::new(chain.config.send_uln, chain.provider.clone()),
+                context: ctx.clone(),
+            });
+
+        // Pick up assignments announced by our own DVN contracts
+        for dvn in chain.config.dvns() {
+            runner = runner.job(ProcessJobAssignedEventHandler {
+                contract: LayerZeroDVNInstance::new(*dvn, chain.provider.clone()),
+                context: ctx.clone(),
+            });
+        }
+    }
+
+    runner.run().await?;
 
     tracing::info!("Exiting...");
     Ok(())
@@ -0,0 +1,158 @@
+//! Manual verification of the packets sent in a single source chain transaction, for operators
+//! investigating stuck messages
+
+use crate::assignment::{packet_of_send, recover_assignment};
+use crate::codec;
+use crate::queue::{Assignment, JobState};
+use crate::retry::{with_retries, Propagate, RetryPolicy};
+use crate::ILayerZeroEndpointV2::PacketSent;
+use crate::SendUln302::DVNFeePaid;
+use crate::{accept_assignment, advance_job_step, dvn_selection, DvnContext, JobError};
+use alloy_primitives::B256;
+use alloy_provider::Provider;
+use gadget_sdk::Error;
+use std::future::Future;
+use std::time::Duration;
+
+/// Retries of a step that keeps hitting temporary errors, such as a receipt the node does not
+/// serve yet, before the command gives up
+const RETRIES: RetryPolicy = RetryPolicy {
+    max_attempts: Some(5),
+    delay: Duration::from_secs(2),
+    max_delay: Duration::from_secs(30),
+};
+
+/// Run the verification pipeline for the assignment in `tx_hash` on the context's source chain,
+/// printing the result of every step and every state the job moves to. With `dry_run`, stops
+/// before sending the verification.
+///
+/// Returns whether the packet is verified on the destination chain.
+pub async fn verify_transaction(
+    tx_hash: B256,
+    dry_run: bool,
+    ctx: &DvnContext,
+) -> Result<bool, Error> {
+    let source = ctx.chains.get(ctx.source_eid)?;
+
+    let receipt = step("Fetch receipt", || async {
+        source
+            .provider
+            .get_transaction_receipt(tx_hash)
+            .await
+            .map_err(|e| Error::Client(e.to_string()))?
+            .ok_or_else(|| JobError::from(Error::Client(format!("{} is not mined", tx_hash))))
+    })
+    .await?;
+    let block_number = receipt
+        .block_number
+        .ok_or_else(|| Error::Client(format!("{} is still pending", tx_hash)))?;
+    let logs = receipt.inner.logs();
+
+    // 1. Find the packets and the DVN fee payment emitted by the transaction
+    let packets = logs
+        .iter()
+        .filter(|log| log.address() == source.config.endpoint)
+        .filter(|log| log.log_decode::<PacketSent>().is_ok())
+        .count();
+    println!("Found {} packets", packets);
+
+    let selection = logs
+        .iter()
+        .filter(|log| log.address() == source.config.send_uln)
+        .find_map(|log| {
+            let fee_paid = log.log_decode::<DVNFeePaid>().ok()?;
+            Some((log, dvn_selection(&fee_paid.inner.data, &source.config)?))
+        });
+    let Some((fee_paid_log, (selection, dvn_index))) = selection else {
+        println!("None of our DVNs was selected in {}", tx_hash);
+        return Ok(false);
+    };
+    println!("Selected as {:?} DVN {}", selection, dvn_index);
+
+    // 2. Recover the assignment for the packet of that send and accept it like the DVNFeePaid
+    // handler does
+    let Some(packet) = packet_of_send(logs, fee_paid_log, source.config.endpoint) else {
+        println!("No valid packet follows the DVNFeePaid event");
+        return Ok(false);
+    };
+    let message_id = codec::packet_message_id(&packet);
+    println!("Message ID {}", message_id);
+
+    let recovered = step("Recover assignment", || async {
+        Ok(recover_assignment(source, tx_hash, logs, message_id).await?)
+    })
+    .await?;
+    let Some((params, assignment_source)) = recovered else {
+        println!("The packet was not assigned to us in {}", tx_hash);
+        return Ok(false);
+    };
+    println!("Assignment recovered from {:?}", assignment_source);
+
+    let assignment = step("Accept assignment", || {
+        accept_assignment(
+            &params,
+            Some((selection, dvn_index)),
+            tx_hash,
+            block_number,
+            ctx,
+        )
+    })
+    .await?;
+    if assignment == Assignment::Parked {
+        println!("No packet in the transaction matches the assignment");
+        return Ok(false);
+    }
+
+    // 3. Drive the job through the pipeline one state at a time
+    loop {
+        let record = ctx
+            .jobs
+            .get(message_id)
+            .await?
+            .ok_or_else(|| Error::Client(format!("No job for {}", message_id)))?;
+        println!("Job is {:?}", record.state);
+
+        let name = match record.state {
+            JobState::Assigned => "Check destination",
+            JobState::Confirming => "Wait for confirmations and verify security",
+            JobState::SecurityChecked if dry_run => {
+                println!("Dry run: stopping before sending the verification");
+                return Ok(false);
+            }
+            JobState::SecurityChecked => "Send verification",
+            JobState::Submitted => "Wait for verification receipt",
+            JobState::Finalized => return Ok(true),
+            JobState::Failed => {
+                println!(
+                    "Job failed: {}",
+                    record.error.as_deref().unwrap_or("unknown error")
+                );
+                return Ok(false);
+            }
+            JobState::Stored => "Resume job",
+        };
+        if let Some(verified) = step(name, || advance_job_step(message_id, ctx)).await? {
+            return Ok(verified);
+        }
+    }
+}
+
+/// Run a pipeline step and print its outcome. Temporary errors are retried as the daemon would
+/// retry them, so only errors that cannot be fixed by waiting, or temporary ones that persist,
+/// end the run.
+async fn step<T, F, Fut>(name: &str, run: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, JobError>>,
+{
+    match with_retries(name, &RETRIES, Propagate, run).await {
+        Ok(value) => {
+            println!("[ok]     {}", name);
+            Ok(value)
+        }
+        Err(e) => {
+            println!("[failed] {}: {}", name, e);
+            Err(e)
+        }
+    }
+}
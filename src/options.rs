@@ -0,0 +1,246 @@
+//! Decoding of LayerZero V2 packet options, matching `SendUlnBase._splitOptions`
+
+use alloy_primitives::{Bytes, U256};
+use gadget_sdk::Error;
+
+/// Legacy options carrying the executor's `lzReceive` gas
+pub const TYPE_1: u16 = 1;
+/// Legacy options carrying the executor's `lzReceive` gas and a native drop
+pub const TYPE_2: u16 = 2;
+/// Options made of worker entries
+pub const TYPE_3: u16 = 3;
+
+/// Worker ID of executor options
+pub const EXECUTOR_WORKER_ID: u8 = 1;
+/// Worker ID of DVN options
+pub const DVN_WORKER_ID: u8 = 2;
+
+// Executor option types produced when converting legacy options
+const OPTION_TYPE_LZRECEIVE: u8 = 1;
+const OPTION_TYPE_NATIVE_DROP: u8 = 2;
+
+/// A single executor option
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutorOption {
+    pub option_type: u8,
+    pub params: Bytes,
+}
+
+/// A single DVN option, addressed to the DVN at `index` in the packet's ULN config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DvnOption {
+    /// Position of the DVN among the required DVNs followed by the optional DVNs
+    pub index: u8,
+    pub option_type: u8,
+    pub params: Bytes,
+}
+
+/// Packet options split by worker
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketOptions {
+    pub executor: Vec<ExecutorOption>,
+    pub dvn: Vec<DvnOption>,
+}
+
+impl PacketOptions {
+    /// Decode options as emitted in `PacketSent.options`. Legacy TYPE_1 and TYPE_2 options are
+    /// converted to executor options, as the send ULN does.
+    pub fn decode(options: &[u8]) -> Result<Self, Error> {
+        if options.is_empty() {
+            return Ok(Self::default());
+        }
+        if options.len() < 2 {
+            return Err(Error::Client("Options too short".into()));
+        }
+
+        match u16::from_be_bytes([options[0], options[1]]) {
+            TYPE_1 => Self::decode_type_1(options),
+            TYPE_2 => Self::decode_type_2(options),
+            TYPE_3 => Self::decode_type_3(options),
+            option_type => Err(Error::Client(format!(
+                "Unsupported options type {}",
+                option_type
+            ))),
+        }
+    }
+
+    /// Options addressed to the DVN at `index`
+    pub fn dvn_options(&self, index: u8) -> impl Iterator<Item = &DvnOption> {
+        self.dvn.iter().filter(move |option| option.index == index)
+    }
+
+    /// TYPE_3 encoding of the options addressed to the DVN at `index`, or empty bytes if there
+    /// are none
+    pub fn encode_dvn_options(&self, index: u8) -> Bytes {
+        let mut encoded = Vec::new();
+        for option in self.dvn_options(index) {
+            if encoded.is_empty() {
+                encoded.extend_from_slice(&TYPE_3.to_be_bytes());
+            }
+            encoded.push(DVN_WORKER_ID);
+            encoded.extend_from_slice(&(option.params.len() as u16 + 2).to_be_bytes());
+            encoded.push(option.index);
+            encoded.push(option.option_type);
+            encoded.extend_from_slice(&option.params);
+        }
+        encoded.into()
+    }
+
+    fn decode_type_1(options: &[u8]) -> Result<Self, Error> {
+        if options.len() != 34 {
+            return Err(Error::Client("Invalid TYPE_1 options length".into()));
+        }
+
+        Ok(Self {
+            executor: vec![lz_receive_option(&options[2..34])?],
+            dvn: Vec::new(),
+        })
+    }
+
+    fn decode_type_2(options: &[u8]) -> Result<Self, Error> {
+        if options.len() <= 66 || options.len() > 98 {
+            return Err(Error::Client("Invalid TYPE_2 options length".into()));
+        }
+
+        // The receiver is right-aligned into a bytes32
+        let amount = read_u128(&options[34..66])?;
+        let mut receiver = [0u8; 32];
+        receiver[32 - (options.len() - 66)..].copy_from_slice(&options[66..]);
+
+        let mut native_drop = amount.to_be_bytes().to_vec();
+        native_drop.extend_from_slice(&receiver);
+
+        Ok(Self {
+            executor: vec![
+                lz_receive_option(&options[2..34])?,
+                ExecutorOption {
+                    option_type: OPTION_TYPE_NATIVE_DROP,
+                    params: native_drop.into(),
+                },
+            ],
+            dvn: Vec::new(),
+        })
+    }
+
+    fn decode_type_3(options: &[u8]) -> Result<Self, Error> {
+        let mut decoded = Self::default();
+        let mut cursor = 2;
+        while cursor < options.len() {
+            if options.len() < cursor + 3 {
+                return Err(Error::Client("Truncated worker option".into()));
+            }
+            let worker_id = options[cursor];
+            let size = u16::from_be_bytes([options[cursor + 1], options[cursor + 2]]) as usize;
+            cursor += 3;
+            if options.len() < cursor + size {
+                return Err(Error::Client("Truncated worker option".into()));
+            }
+            let option = &options[cursor..cursor + size];
+            cursor += size;
+
+            match worker_id {
+                EXECUTOR_WORKER_ID if !option.is_empty() => decoded.executor.push(ExecutorOption {
+                    option_type: option[0],
+                    params: Bytes::copy_from_slice(&option[1..]),
+                }),
+                DVN_WORKER_ID if option.len() >= 2 => decoded.dvn.push(DvnOption {
+                    index: option[0],
+                    option_type: option[1],
+                    params: Bytes::copy_from_slice(&option[2..]),
+                }),
+                EXECUTOR_WORKER_ID | DVN_WORKER_ID => {
+                    return Err(Error::Client(format!(
+                        "Invalid option size {} for worker {}",
+                        size, worker_id
+                    )))
+                }
+                worker_id => return Err(Error::Client(format!("Invalid worker ID {}", worker_id))),
+            }
+        }
+
+        Ok(decoded)
+    }
+}
+
+/// Executor `lzReceive` option for the legacy execution gas in `gas`
+fn lz_receive_option(gas: &[u8]) -> Result<ExecutorOption, Error> {
+    Ok(ExecutorOption {
+        option_type: OPTION_TYPE_LZRECEIVE,
+        params: Bytes::copy_from_slice(&read_u128(gas)?.to_be_bytes()),
+    })
+}
+
+fn read_u128(word: &[u8]) -> Result<u128, Error> {
+    u128::try_from(U256::from_be_slice(word))
+        .map_err(|_| Error::Client("Legacy option value exceeds uint128".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::hex;
+
+    #[test]
+    fn test_decode_type_3() {
+        // Executor lzReceive(200000), DVN 0 option type 1, DVN 1 option type 1 with params
+        let options = hex!(
+            "0003"
+            "01" "0011" "01" "00000000000000000000000000030d40"
+            "02" "0002" "00" "01"
+            "02" "0004" "01" "01" "abcd"
+        );
+
+        let decoded = PacketOptions::decode(&options).unwrap();
+        assert_eq!(
+            decoded.executor,
+            [ExecutorOption {
+                option_type: 1,
+                params: Bytes::copy_from_slice(&200_000u128.to_be_bytes()),
+            }]
+        );
+        assert_eq!(decoded.dvn.len(), 2);
+
+        let ours = decoded.dvn_options(1).collect::<Vec<_>>();
+        assert_eq!(
+            ours,
+            [&DvnOption {
+                index: 1,
+                option_type: 1,
+                params: Bytes::copy_from_slice(&hex!("abcd")),
+            }]
+        );
+        assert_eq!(
+            decoded.encode_dvn_options(1),
+            Bytes::copy_from_slice(&hex!("0003" "02" "0004" "01" "01" "abcd"))
+        );
+        assert!(decoded.encode_dvn_options(2).is_empty());
+    }
+
+    #[test]
+    fn test_decode_legacy_options() {
+        let mut type_1 = hex!("0001").to_vec();
+        type_1.extend_from_slice(&U256::from(200_000u64).to_be_bytes::<32>());
+        let decoded = PacketOptions::decode(&type_1).unwrap();
+        assert_eq!(decoded.executor.len(), 1);
+        assert!(decoded.dvn.is_empty());
+
+        let mut type_2 = hex!("0002").to_vec();
+        type_2.extend_from_slice(&U256::from(200_000u64).to_be_bytes::<32>());
+        type_2.extend_from_slice(&U256::from(1_000u64).to_be_bytes::<32>());
+        type_2.extend_from_slice(&[0x11; 20]);
+        let decoded = PacketOptions::decode(&type_2).unwrap();
+        assert_eq!(decoded.executor.len(), 2);
+        assert_eq!(&decoded.executor[1].params[..16], &1_000u128.to_be_bytes());
+        assert_eq!(&decoded.executor[1].params[16..28], &[0u8; 12]);
+        assert_eq!(&decoded.executor[1].params[28..], &[0x11; 20]);
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_options() {
+        assert!(PacketOptions::decode(&hex!("0004")).is_err());
+        assert!(PacketOptions::decode(&hex!("0003" "02" "0004" "01" "01")).is_err());
+        assert!(PacketOptions::decode(&hex!("0003" "03" "0001" "00")).is_err());
+        assert!(PacketOptions::decode(&hex!("0003" "02" "0001" "00")).is_err());
+        assert!(PacketOptions::decode(&hex!("0001" "00")).is_err());
+    }
+}
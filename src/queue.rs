@@ -0,0 +1,478 @@
+//! Durable per-message job state, so verifications survive a restart of the DVN
+
+use crate::store::{PacketStore, StoredPacket};
+use alloy_primitives::{TxHash, B256};
+use gadget_sdk::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Progress of a single message through the DVN. The discriminants are persisted by the sled
+/// backend's status index, so they must never change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum JobState {
+    /// The `PacketSent` event was stored, but we have not been assigned the packet
+    Stored = 0,
+    /// We were assigned to verify the packet
+    Assigned = 1,
+    /// Waiting for the packet's block to reach its required confirmations
+    Confirming = 2,
+    /// The packet is confirmed and passed the configured security verification
+    SecurityChecked = 3,
+    /// A `verifyMessageHash` transaction was sent to the destination chain
+    Submitted = 4,
+    /// The packet is verified on the destination chain
+    Finalized = 5,
+    /// Verification was abandoned
+    Failed = 6,
+}
+
+impl JobState {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Finalized | Self::Failed)
+    }
+
+    /// Whether a job in this state was assigned to us and still has work left
+    pub fn is_in_progress(&self) -> bool {
+        !self.is_terminal() && *self != Self::Stored
+    }
+}
+
+/// How the OApp's ULN config selected our DVN for a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DvnSelection {
+    Required,
+    Optional,
+}
+
+/// Persisted state of a single message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub message_id: B256,
+    pub state: JobState,
+    /// Confirmations requested in the job's `AssignJobParam`, set once assigned
+    pub required_confirmations: Option<u64>,
+    /// Whether we were selected as a required or optional DVN, set once assigned
+    pub selection: Option<DvnSelection>,
+    /// Our position among the packet's required DVNs followed by its optional DVNs, which
+    /// addresses our DVN options, set once assigned
+    #[serde(default)]
+    pub dvn_index: Option<u8>,
+    /// Hash of the `verifyMessageHash` transaction, set once submitted
+    pub transaction_hash: Option<TxHash>,
+    /// Reason the job failed, if it did
+    pub error: Option<String>,
+    pub updated_at: u64,
+}
+
+impl JobRecord {
+    pub fn new(message_id: B256) -> Self {
+        Self {
+            message_id,
+            state: JobState::Stored,
+            required_confirmations: None,
+            selection: None,
+            dvn_index: None,
+            transaction_hash: None,
+            error: None,
+            updated_at: now(),
+        }
+    }
+}
+
+/// Where a job stands once its assignment was persisted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assignment {
+    /// Newly assigned, with its packet stored and ready to be verified
+    Started,
+    /// Newly assigned, but parked until its packet is stored
+    Parked,
+    /// Already assigned by an earlier event, and now in the given state
+    Existing(JobState),
+}
+
+/// Job records keyed by message ID, kept in the packet store alongside the packets, whose status
+/// index mirrors the job states
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    packets: Arc<dyn PacketStore>,
+    /// Serializes packet insertions, assignments and requeues, so a job reported by several events
+    /// is only started once, an assignment either finds its packet or is found by it, and no job
+    /// is started while its packet is evicted
+    assign_lock: Arc<Mutex<()>>,
+    /// Jobs currently driven by a task, so concurrent runs of the same job do not step it twice
+    running: Arc<std::sync::Mutex<HashSet<B256>>>,
+}
+
+/// Marks a job as being driven by the task holding it, until dropped
+#[derive(Debug)]
+pub struct RunningJob {
+    message_id: B256,
+    running: Arc<std::sync::Mutex<HashSet<B256>>>,
+}
+
+impl Drop for RunningJob {
+    fn drop(&mut self) {
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.message_id);
+    }
+}
+
+impl JobQueue {
+    pub fn new(packets: Arc<dyn PacketStore>) -> Self {
+        Self {
+            packets,
+            assign_lock: Arc::new(Mutex::new(())),
+            running: Default::default(),
+        }
+    }
+
+    /// Claim the job for `message_id` for the calling task, unless another task is driving it
+    pub fn try_run(&self, message_id: B256) -> Option<RunningJob> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        running.insert(message_id).then(|| RunningJob {
+            message_id,
+            running: self.running.clone(),
+        })
+    }
+
+    pub async fn get(&self, message_id: B256) -> Result<Option<JobRecord>, Error> {
+        self.packets.get_job(message_id).await
+    }
+
+    /// Hold off assignments and requeues until the returned guard is dropped, so jobs that are not
+    /// in progress stay that way
+    pub async fn lock_assignments(&self) -> MutexGuard<'_, ()> {
+        self.assign_lock.lock().await
+    }
+
+    /// Persist `record`, keeping the status of its packet in sync with its state
+    pub async fn update(&self, mut record: JobRecord) -> Result<JobRecord, Error> {
+        record.updated_at = now();
+        self.packets.put_job(&record).await?;
+        Ok(record)
+    }
+
+    /// Move a job we were assigned back to [`JobState::Assigned`], so it is verified again from
+    /// scratch the next time it is run
+    pub async fn requeue(&self, message_id: B256) -> Result<JobRecord, Error> {
+        let _guard = self.assign_lock.lock().await;
+        let mut record = self
+            .get(message_id)
+            .await?
+            .ok_or_else(|| Error::Client(format!("No job for {}", message_id)))?;
+        if record.required_confirmations.is_none() {
+            return Err(Error::Client(format!(
+                "Packet {} was never assigned to us",
+                message_id
+            )));
+        }
+
+        record.state = JobState::Assigned;
+        record.transaction_hash = None;
+        record.error = None;
+        self.update(record).await
+    }
+
+    /// Delete the job for `message_id`
+    pub async fn remove(&self, message_id: B256) -> Result<(), Error> {
+        self.packets.remove_job(message_id).await
+    }
+
+    /// Move the job for `message_id` to `state`, creating its record if needed
+    pub async fn transition(&self, message_id: B256, state: JobState) -> Result<JobRecord, Error> {
+        let mut record = self
+            .get(message_id)
+            .await?
+            .unwrap_or_else(|| JobRecord::new(message_id));
+        tracing::debug!(
+            "Job {} moved from {:?} to {:?}",
+            message_id,
+            record.state,
+            state
+        );
+        record.state = state;
+        self.update(record).await
+    }
+
+    /// Store `packet` under `message_id` and return its job, creating a [`JobState::Stored`]
+    /// record if there is none, along with whether the packet was newly inserted. Runs under the
+    /// assignment lock, so an assignment persisted concurrently is either returned here or sees
+    /// the packet.
+    pub async fn store_packet(
+        &self,
+        message_id: B256,
+        packet: &StoredPacket,
+    ) -> Result<(JobRecord, bool), Error> {
+        let _guard = self.assign_lock.lock().await;
+        let inserted = self.packets.insert(message_id, packet).await?;
+        let record = match self.get(message_id).await? {
+            // Index the packet under the state of an assignment processed before it
+            Some(record) => {
+                self.packets.set_status(message_id, record.state).await?;
+                record
+            }
+            None => self.update(JobRecord::new(message_id)).await?,
+        };
+
+        Ok((record, inserted))
+    }
+
+    /// Return the job for `message_id`, creating a [`JobState::Stored`] record if there is none.
+    /// Runs under the assignment lock, so it never overwrites a concurrent assignment.
+    pub async fn create_if_absent(&self, message_id: B256) -> Result<(JobRecord, bool), Error> {
+        let _guard = self.assign_lock.lock().await;
+        match self.get(message_id).await? {
+            Some(record) => Ok((record, false)),
+            None => Ok((self.update(JobRecord::new(message_id)).await?, true)),
+        }
+    }
+
+    /// Move the job for `message_id` to [`JobState::Assigned`] unless it is already in progress or
+    /// finalized, returning its record and whether it can be started now that its packet is
+    /// stored, or is parked until [`Self::store_packet`] sees its packet
+    pub async fn assign(
+        &self,
+        message_id: B256,
+        required_confirmations: u64,
+        selection: Option<(DvnSelection, u8)>,
+    ) -> Result<(JobRecord, Assignment), Error> {
+        let _guard = self.assign_lock.lock().await;
+        let mut record = self
+            .get(message_id)
+            .await?
+            .unwrap_or_else(|| JobRecord::new(message_id));
+        if record.state.is_in_progress() || record.state == JobState::Finalized {
+            let state = record.state;
+            return Ok((record, Assignment::Existing(state)));
+        }
+
+        record.state = JobState::Assigned;
+        record.required_confirmations = Some(required_confirmations);
+        record.selection = selection.map(|(selection, _)| selection);
+        record.dvn_index = selection.map(|(_, index)| index);
+        record.error = None;
+        let record = self.update(record).await?;
+
+        let assignment = match self.packets.get(message_id).await? {
+            Some(_) => Assignment::Started,
+            None => Assignment::Parked,
+        };
+        Ok((record, assignment))
+    }
+
+    /// Mark the job for `message_id` as failed with `error`
+    pub async fn fail(&self, message_id: B256, error: String) -> Result<JobRecord, Error> {
+        let mut record = self
+            .get(message_id)
+            .await?
+            .unwrap_or_else(|| JobRecord::new(message_id));
+        record.state = JobState::Failed;
+        record.error = Some(error);
+        self.update(record).await
+    }
+
+    /// All jobs that were assigned to us and have not reached a terminal state
+    pub async fn pending(&self) -> Result<Vec<JobRecord>, Error> {
+        self.packets.pending_jobs().await
+    }
+
+    /// Every job, including packets we were never assigned
+    pub async fn all(&self) -> Result<Vec<JobRecord>, Error> {
+        self.packets.jobs().await
+    }
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_packets::stored_packet;
+    use crate::store::{self, StorageBackend};
+    use std::path::Path;
+
+    fn queue(dir: &Path) -> JobQueue {
+        JobQueue::new(store::open(StorageBackend::Sqlite, dir).unwrap())
+    }
+
+    #[test]
+    fn test_state_discriminants_are_stable() {
+        let states = [
+            (JobState::Stored, 0),
+            (JobState::Assigned, 1),
+            (JobState::Confirming, 2),
+            (JobState::SecurityChecked, 3),
+            (JobState::Submitted, 4),
+            (JobState::Finalized, 5),
+            (JobState::Failed, 6),
+        ];
+        for (state, discriminant) in states {
+            assert_eq!(state as u8, discriminant, "{:?}", state);
+        }
+    }
+
+    fn pending_ids(records: &[JobRecord]) -> Vec<B256> {
+        records.iter().map(|record| record.message_id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_assignment_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = queue(dir.path());
+        let id = B256::repeat_byte(0x01);
+
+        let (record, created) = jobs.create_if_absent(id).await.unwrap();
+        assert!(created);
+        assert_eq!(record.state, JobState::Stored);
+        assert!(jobs.pending().await.unwrap().is_empty());
+
+        let (record, assignment) = jobs
+            .assign(id, 15, Some((DvnSelection::Required, 2)))
+            .await
+            .unwrap();
+        assert_eq!(assignment, Assignment::Parked);
+        assert_eq!(record.state, JobState::Assigned);
+        assert_eq!(record.required_confirmations, Some(15));
+        assert_eq!(record.dvn_index, Some(2));
+        assert_eq!(pending_ids(&jobs.pending().await.unwrap()), [id]);
+
+        // The same assignment reported by another event does not restart the job
+        jobs.transition(id, JobState::Confirming).await.unwrap();
+        let (record, assignment) = jobs.assign(id, 15, None).await.unwrap();
+        assert_eq!(assignment, Assignment::Existing(JobState::Confirming));
+        assert_eq!(record.state, JobState::Confirming);
+
+        // Nor does a packet stored after its assignment
+        let (record, created) = jobs.create_if_absent(id).await.unwrap();
+        assert!(!created);
+        assert_eq!(record.state, JobState::Confirming);
+
+        jobs.transition(id, JobState::Finalized).await.unwrap();
+        assert!(jobs.pending().await.unwrap().is_empty());
+        assert_eq!(
+            jobs.assign(id, 15, None).await.unwrap().1,
+            Assignment::Existing(JobState::Finalized)
+        );
+        assert_eq!(jobs.all().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_jobs_can_be_reassigned_and_requeued() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = queue(dir.path());
+        let id = B256::repeat_byte(0x02);
+
+        // A job that was never assigned cannot be requeued
+        jobs.create_if_absent(id).await.unwrap();
+        assert!(jobs.requeue(id).await.is_err());
+
+        jobs.assign(id, 1, None).await.unwrap();
+        let record = jobs.fail(id, "reverted".into()).await.unwrap();
+        assert_eq!(record.state, JobState::Failed);
+        assert_eq!(record.error.as_deref(), Some("reverted"));
+        assert!(jobs.pending().await.unwrap().is_empty());
+
+        let record = jobs.requeue(id).await.unwrap();
+        assert_eq!(record.state, JobState::Assigned);
+        assert_eq!(record.error, None);
+        assert_eq!(pending_ids(&jobs.pending().await.unwrap()), [id]);
+
+        jobs.fail(id, "reverted".into()).await.unwrap();
+        let (record, assignment) = jobs.assign(id, 1, None).await.unwrap();
+        assert_eq!(assignment, Assignment::Parked);
+        assert_eq!(record.error, None);
+
+        jobs.remove(id).await.unwrap();
+        assert!(jobs.get(id).await.unwrap().is_none());
+        assert!(jobs.pending().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_assignment_and_packet_see_each_other() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = queue(dir.path());
+
+        // A packet stored first lets its assignment start right away
+        let (stored_first, packet) = stored_packet(1, 30101);
+        let (record, inserted) = jobs.store_packet(stored_first, &packet).await.unwrap();
+        assert!(inserted);
+        assert_eq!(record.state, JobState::Stored);
+        let (_, assignment) = jobs.assign(stored_first, 1, None).await.unwrap();
+        assert_eq!(assignment, Assignment::Started);
+
+        // An assignment persisted first is parked, and handed back once its packet is stored
+        let (assigned_first, packet) = stored_packet(2, 30101);
+        let (_, assignment) = jobs.assign(assigned_first, 1, None).await.unwrap();
+        assert_eq!(assignment, Assignment::Parked);
+        let (record, inserted) = jobs.store_packet(assigned_first, &packet).await.unwrap();
+        assert!(inserted);
+        assert_eq!(record.state, JobState::Assigned);
+        assert!(jobs
+            .packets
+            .find_by_status(JobState::Assigned)
+            .await
+            .unwrap()
+            .contains(&assigned_first));
+    }
+
+    #[test]
+    fn test_only_one_task_runs_a_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = queue(dir.path());
+        let id = B256::repeat_byte(0x07);
+
+        let running = jobs.try_run(id).unwrap();
+        assert!(jobs.try_run(id).is_none());
+        assert!(jobs.try_run(B256::repeat_byte(0x08)).is_some());
+
+        drop(running);
+        assert!(jobs.try_run(id).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_resumes_jobs_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let (confirming, submitted, parked, finalized) = (
+            B256::repeat_byte(0x03),
+            B256::repeat_byte(0x04),
+            B256::repeat_byte(0x05),
+            B256::repeat_byte(0x06),
+        );
+
+        {
+            let jobs = queue(dir.path());
+            for id in [confirming, submitted, parked, finalized] {
+                jobs.assign(id, 1, None).await.unwrap();
+            }
+            jobs.transition(confirming, JobState::Confirming)
+                .await
+                .unwrap();
+            let mut record = jobs.get(submitted).await.unwrap().unwrap();
+            record.state = JobState::Submitted;
+            record.transaction_hash = Some(TxHash::repeat_byte(0xaa));
+            jobs.update(record).await.unwrap();
+            jobs.transition(finalized, JobState::Finalized)
+                .await
+                .unwrap();
+        }
+
+        let jobs = queue(dir.path());
+        let mut pending = jobs.pending().await.unwrap();
+        pending.sort_by_key(|record| record.message_id);
+        assert_eq!(pending_ids(&pending), [confirming, submitted, parked]);
+        assert_eq!(pending[0].state, JobState::Confirming);
+        assert_eq!(pending[1].state, JobState::Submitted);
+        assert_eq!(pending[1].transaction_hash, Some(TxHash::repeat_byte(0xaa)));
+        assert_eq!(pending[2].state, JobState::Assigned);
+    }
+}
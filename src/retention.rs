@@ -0,0 +1,431 @@
+//! Retention of stored packets: packets we are never assigned are discarded, finalized ones are
+//! archived, and the store is capped at a maximum number of packets
+
+use crate::queue::{JobQueue, JobRecord, JobState};
+use crate::store::{PacketStore, StoredPacket};
+use alloy_primitives::B256;
+use gadget_sdk::Error;
+use prometheus::{IntCounter, IntCounterVec, Opts, Registry};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::{interval, Duration};
+
+/// Retention settings. Fields left out of the config keep their default, and retention only runs
+/// once `enabled` is set, so existing stores are not trimmed without the operator opting in.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Whether to periodically collect stored packets
+    pub enabled: bool,
+    /// Seconds between collections
+    pub interval_secs: u64,
+    /// Seconds after which a packet we were not assigned is discarded
+    pub unassigned_ttl_secs: u64,
+    /// Seconds after finalization at which a packet is archived
+    pub archive_after_secs: u64,
+    /// Maximum number of stored packets. The oldest packets whose jobs are not in progress are
+    /// evicted beyond it.
+    pub max_packets: Option<usize>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 60 * 60,
+            unassigned_ttl_secs: 24 * 60 * 60,
+            archive_after_secs: 7 * 24 * 60 * 60,
+            max_packets: None,
+        }
+    }
+}
+
+/// Why a packet was removed from the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// We were not assigned the packet within the retention window, so it is discarded
+    Unassigned,
+    /// The packet was finalized long enough ago, so it is archived
+    Finalized,
+    /// The store is over capacity. Packets with a terminal job are archived, others discarded.
+    OverCapacity,
+}
+
+/// Running totals of packets removed from the store, exported as the
+/// `dvn_retention_packets_removed_total` counter labeled by `reason`
+#[derive(Debug, Clone)]
+pub struct RetentionMetrics {
+    pub discarded_unassigned: IntCounter,
+    pub archived_finalized: IntCounter,
+    pub evicted_over_capacity: IntCounter,
+}
+
+impl RetentionMetrics {
+    /// Create the counters and register them with `registry`
+    pub fn register(registry: &Registry) -> Result<Self, Error> {
+        let removed = IntCounterVec::new(
+            Opts::new(
+                "dvn_retention_packets_removed_total",
+                "Stored packets removed by retention",
+            ),
+            &["reason"],
+        )
+        .and_then(|removed| {
+            registry.register(Box::new(removed.clone()))?;
+            Ok(removed)
+        })
+        .map_err(|e| Error::Client(format!("Failed to register retention metrics: {}", e)))?;
+
+        Ok(Self {
+            discarded_unassigned: removed.with_label_values(&["unassigned"]),
+            archived_finalized: removed.with_label_values(&["finalized"]),
+            evicted_over_capacity: removed.with_label_values(&["over_capacity"]),
+        })
+    }
+
+    fn record(&self, eviction: Eviction) {
+        match eviction {
+            Eviction::Unassigned => &self.discarded_unassigned,
+            Eviction::Finalized => &self.archived_finalized,
+            Eviction::OverCapacity => &self.evicted_over_capacity,
+        }
+        .inc();
+    }
+}
+
+/// Collects stored packets according to a [`RetentionConfig`]
+#[derive(Debug, Clone)]
+pub struct Retention {
+    config: RetentionConfig,
+    store: Arc<dyn PacketStore>,
+    jobs: JobQueue,
+    /// JSON lines file finalized packets are archived to
+    archive_path: PathBuf,
+    pub metrics: RetentionMetrics,
+}
+
+/// A stored packet considered for eviction
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    message_id: B256,
+    state: JobState,
+    /// When the packet was stored
+    stored_at: u64,
+    /// When its job last changed state
+    updated_at: u64,
+}
+
+impl Retention {
+    pub fn new(
+        config: RetentionConfig,
+        store: Arc<dyn PacketStore>,
+        jobs: JobQueue,
+        data_dir: &Path,
+        registry: &Registry,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            store,
+            jobs,
+            archive_path: data_dir.join("archive").join("packets.jsonl"),
+            metrics: RetentionMetrics::register(registry)?,
+        })
+    }
+
+    /// Collect the store every `interval_secs`, forever
+    pub async fn run(self) {
+        let mut ticker = interval(Duration::from_secs(self.config.interval_secs.max(1)));
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.collect().await {
+                tracing::error!("Packet retention failed: {}", e);
+            }
+        }
+    }
+
+    /// Remove every packet due for eviction, returning how many were removed
+    pub async fn collect(&self) -> Result<usize, Error> {
+        let mut candidates = Vec::new();
+        for message_id in self.store.message_ids().await? {
+            let Some(stored) = self.store.get(message_id).await? else {
+                continue;
+            };
+            let record = self.jobs.get(message_id).await?;
+            candidates.push(Candidate {
+                message_id,
+                state: record.as_ref().map_or(JobState::Stored, |r| r.state),
+                stored_at: stored.timestamp,
+                updated_at: record.as_ref().map_or(stored.timestamp, |r| r.updated_at),
+            });
+        }
+
+        let mut removed = 0;
+        for (message_id, eviction) in plan(&candidates, &self.config, now()) {
+            if self.evict(message_id, eviction).await? {
+                self.metrics.record(eviction);
+                removed += 1;
+            }
+        }
+
+        if removed > 0 {
+            tracing::info!(
+                "Removed {} stored packets (totals: {} unassigned discarded, {} finalized archived, {} evicted over capacity)",
+                removed,
+                self.metrics.discarded_unassigned.get(),
+                self.metrics.archived_finalized.get(),
+                self.metrics.evicted_over_capacity.get(),
+            );
+        }
+        Ok(removed)
+    }
+
+    /// Remove a packet planned for eviction, returning whether it was removed. Its job is read
+    /// again while assignments are held off, and the packet is kept if the job moved on since
+    /// the plan was made.
+    async fn evict(&self, message_id: B256, eviction: Eviction) -> Result<bool, Error> {
+        let _guard = self.jobs.lock_assignments().await;
+        let record = self.jobs.get(message_id).await?;
+        let state = record.as_ref().map_or(JobState::Stored, |r| r.state);
+        let evictable = match eviction {
+            Eviction::Unassigned => state == JobState::Stored,
+            Eviction::Finalized => state == JobState::Finalized,
+            Eviction::OverCapacity => !state.is_in_progress(),
+        };
+        if !evictable {
+            tracing::debug!(
+                "Keeping packet {}, whose job moved to {:?}",
+                message_id,
+                state
+            );
+            return Ok(false);
+        }
+
+        if eviction == Eviction::Finalized
+            || (eviction == Eviction::OverCapacity && state.is_terminal())
+        {
+            if let Some(packet) = self.store.get(message_id).await? {
+                self.archive(message_id, packet, record)?;
+            }
+        }
+
+        tracing::debug!("Evicting packet {} ({:?})", message_id, eviction);
+        self.store.remove(message_id).await?;
+        self.jobs.remove(message_id).await?;
+        Ok(true)
+    }
+
+    fn archive(
+        &self,
+        message_id: B256,
+        packet: StoredPacket,
+        job: Option<JobRecord>,
+    ) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct Archived {
+            message_id: B256,
+            packet: StoredPacket,
+            job: Option<JobRecord>,
+        }
+
+        let line = serde_json::to_string(&Archived {
+            message_id,
+            packet,
+            job,
+        })
+        .map_err(|e| Error::Client(format!("Failed to serialize archived packet: {}", e)))?;
+
+        let write = || -> std::io::Result<()> {
+            if let Some(dir) = self.archive_path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.archive_path)?;
+            writeln!(file, "{}", line)
+        };
+        write().map_err(|e| {
+            Error::Client(format!(
+                "Failed to archive packet to {}: {}",
+                self.archive_path.display(),
+                e
+            ))
+        })
+    }
+}
+
+/// Packets to evict at time `now`. Packets whose jobs are in progress are never evicted.
+fn plan(candidates: &[Candidate], config: &RetentionConfig, now: u64) -> Vec<(B256, Eviction)> {
+    let mut evictions = Vec::new();
+    let mut kept = Vec::new();
+    for candidate in candidates {
+        let age = now.saturating_sub(candidate.stored_at);
+        let finalized_age = now.saturating_sub(candidate.updated_at);
+        match candidate.state {
+            JobState::Stored if age >= config.unassigned_ttl_secs => {
+                evictions.push((candidate.message_id, Eviction::Unassigned))
+            }
+            JobState::Finalized if finalized_age >= config.archive_after_secs => {
+                evictions.push((candidate.message_id, Eviction::Finalized))
+            }
+            _ => kept.push(candidate),
+        }
+    }
+
+    if let Some(max_packets) = config.max_packets {
+        let mut evictable = kept
+            .into_iter()
+            .filter(|candidate| !candidate.state.is_in_progress())
+            .collect::<Vec<_>>();
+        let remaining = candidates.len() - evictions.len();
+        evictable.sort_by_key(|candidate| candidate.stored_at);
+        evictions.extend(
+            evictable
+                .into_iter()
+                .take(remaining.saturating_sub(max_packets))
+                .map(|candidate| (candidate.message_id, Eviction::OverCapacity)),
+        );
+    }
+
+    evictions
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_packets::stored_packet;
+    use crate::queue::DvnSelection;
+    use crate::store::{self, StorageBackend};
+
+    const HOUR: u64 = 60 * 60;
+    const NOW: u64 = 1_000 * HOUR;
+
+    fn candidate(id: u8, state: JobState, hours_ago: u64) -> Candidate {
+        Candidate {
+            message_id: B256::repeat_byte(id),
+            state,
+            stored_at: NOW - hours_ago * HOUR,
+            updated_at: NOW - hours_ago * HOUR,
+        }
+    }
+
+    fn config(max_packets: Option<usize>) -> RetentionConfig {
+        RetentionConfig {
+            unassigned_ttl_secs: 24 * HOUR,
+            archive_after_secs: 48 * HOUR,
+            max_packets,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_applies_retention_windows() {
+        let candidates = [
+            candidate(1, JobState::Stored, 25),
+            candidate(2, JobState::Stored, 1),
+            candidate(3, JobState::Finalized, 49),
+            candidate(4, JobState::Finalized, 25),
+            candidate(5, JobState::Confirming, 100),
+            candidate(6, JobState::Failed, 100),
+        ];
+
+        assert_eq!(
+            plan(&candidates, &config(None), NOW),
+            [
+                (B256::repeat_byte(1), Eviction::Unassigned),
+                (B256::repeat_byte(3), Eviction::Finalized),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_caps_store_size() {
+        let candidates = [
+            candidate(1, JobState::Confirming, 10),
+            candidate(2, JobState::Failed, 8),
+            candidate(3, JobState::Stored, 9),
+            candidate(4, JobState::Finalized, 2),
+            candidate(5, JobState::Stored, 1),
+        ];
+
+        // In-progress jobs are kept even when they are the oldest
+        assert_eq!(
+            plan(&candidates, &config(Some(3)), NOW),
+            [
+                (B256::repeat_byte(3), Eviction::OverCapacity),
+                (B256::repeat_byte(2), Eviction::OverCapacity),
+            ]
+        );
+        assert!(plan(&candidates, &config(Some(5)), NOW).is_empty());
+    }
+
+    #[test]
+    fn test_partial_config_keeps_defaults() {
+        let config: RetentionConfig = serde_json::from_str(r#"{ "max_packets": 10 }"#).unwrap();
+        assert!(!config.enabled);
+        assert_eq!(
+            config.interval_secs,
+            RetentionConfig::default().interval_secs
+        );
+        assert_eq!(config.max_packets, Some(10));
+    }
+
+    async fn retention(dir: &Path, config: RetentionConfig) -> (Retention, B256) {
+        let packets = store::open(StorageBackend::Sqlite, dir).unwrap();
+        let jobs = JobQueue::new(packets.clone());
+        let (message_id, stored) = stored_packet(1, 30101);
+        packets.insert(message_id, &stored).await.unwrap();
+        jobs.create_if_absent(message_id).await.unwrap();
+
+        let retention = Retention::new(config, packets, jobs, dir, &Registry::new()).unwrap();
+        (retention, message_id)
+    }
+
+    #[tokio::test]
+    async fn test_keeps_packets_assigned_after_planning() {
+        let dir = tempfile::tempdir().unwrap();
+        let (retention, message_id) = retention(dir.path(), config(None)).await;
+
+        retention
+            .jobs
+            .assign(message_id, 15, Some((DvnSelection::Required, 0)))
+            .await
+            .unwrap();
+        assert!(!retention
+            .evict(message_id, Eviction::Unassigned)
+            .await
+            .unwrap());
+        assert!(retention.store.get(message_id).await.unwrap().is_some());
+        assert!(retention.jobs.get(message_id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_collect_counts_evictions() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Registry::new();
+        let (mut retention, message_id) = retention(dir.path(), config(None)).await;
+        retention.metrics = RetentionMetrics::register(&registry).unwrap();
+
+        assert_eq!(retention.collect().await.unwrap(), 1);
+        assert!(retention.store.get(message_id).await.unwrap().is_none());
+        assert!(retention.jobs.get(message_id).await.unwrap().is_none());
+        assert_eq!(retention.metrics.discarded_unassigned.get(), 1);
+        assert_eq!(retention.metrics.archived_finalized.get(), 0);
+
+        let families = registry.gather();
+        assert_eq!(families.len(), 1);
+        assert_eq!(
+            families[0].get_name(),
+            "dvn_retention_packets_removed_total"
+        );
+    }
+}
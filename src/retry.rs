@@ -0,0 +1,184 @@
+//! Retrying of steps that may hit temporary errors, shared by the job runner, the backfill and
+//! the `verify-tx` command
+
+use crate::JobError;
+use gadget_sdk::Error;
+use std::future::Future;
+use std::time::Duration;
+
+/// How often and how long temporary errors are retried
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    /// Attempts before a temporary error that persists is returned, or `None` to retry until the
+    /// step succeeds or fails for good
+    pub max_attempts: Option<u32>,
+    /// Delay before the first retry, doubled with every retry
+    pub delay: Duration,
+    /// Longest delay between two attempts
+    pub max_delay: Duration,
+}
+
+/// What [`with_retries`] does with an error retrying cannot fix
+pub(crate) trait OnFatal<T> {
+    /// Result of a step that succeeded or whose error was handled
+    type Output;
+
+    fn succeeded(&self, value: T) -> Self::Output;
+
+    fn failed(&self, step: &str, e: Error) -> Result<Self::Output, Error>;
+}
+
+/// Log the error and move on, with `None` as the step's result
+pub(crate) struct Skip;
+
+impl<T> OnFatal<T> for Skip {
+    type Output = Option<T>;
+
+    fn succeeded(&self, value: T) -> Option<T> {
+        Some(value)
+    }
+
+    fn failed(&self, step: &str, e: Error) -> Result<Option<T>, Error> {
+        tracing::warn!("Skipping {}: {}", step, e);
+        Ok(None)
+    }
+}
+
+/// Return the error
+pub(crate) struct Propagate;
+
+impl<T> OnFatal<T> for Propagate {
+    type Output = T;
+
+    fn succeeded(&self, value: T) -> T {
+        value
+    }
+
+    fn failed(&self, _step: &str, e: Error) -> Result<T, Error> {
+        Err(e)
+    }
+}
+
+/// Run `step`, retrying its temporary errors as `policy` allows. Fatal errors are handled by
+/// `on_fatal`, while temporary errors that persist past the last attempt are returned.
+pub(crate) async fn with_retries<T, P, F, Fut>(
+    step: &str,
+    policy: &RetryPolicy,
+    on_fatal: P,
+    mut run: F,
+) -> Result<P::Output, Error>
+where
+    P: OnFatal<T>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, JobError>>,
+{
+    let mut attempt = 1;
+    let mut delay = policy.delay;
+    loop {
+        match run().await {
+            Ok(value) => return Ok(on_fatal.succeeded(value)),
+            Err(JobError::Fatal(e)) => return on_fatal.failed(step, e),
+            Err(JobError::Retryable(e))
+                if policy.max_attempts.is_some_and(|max| attempt >= max) =>
+            {
+                return Err(e)
+            }
+            Err(JobError::Retryable(e)) => {
+                tracing::warn!(
+                    "{} hit a temporary error (attempt {}), retrying in {:?}: {}",
+                    step,
+                    attempt,
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(policy.max_delay);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_attempts: Some(5),
+        delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    fn temporary() -> JobError {
+        JobError::Retryable(Error::Client("timeout".into()))
+    }
+
+    fn fatal() -> JobError {
+        JobError::Fatal(Error::Client("refused".into()))
+    }
+
+    #[tokio::test]
+    async fn test_retries_temporary_errors() {
+        let attempts = &AtomicU32::new(0);
+        let result = with_retries("step", &POLICY, Propagate, || async move {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                return Err(temporary());
+            }
+            Ok(())
+        })
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stops_after_persistent_temporary_errors() {
+        let attempts = &AtomicU32::new(0);
+        let result = with_retries("step", &POLICY, Skip, || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(temporary())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_fatal_error_policies() {
+        let attempts = &AtomicU32::new(0);
+        let skipped = with_retries("step", &POLICY, Skip, || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(fatal())
+        })
+        .await;
+        assert_eq!(skipped.unwrap(), None);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let propagated = with_retries("step", &POLICY, Propagate, || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(fatal())
+        })
+        .await;
+        assert!(propagated.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_without_limit() {
+        let policy = RetryPolicy {
+            max_attempts: None,
+            ..POLICY
+        };
+        let attempts = &AtomicU32::new(0);
+        let result = with_retries("step", &policy, Propagate, || async move {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 20 {
+                return Err(temporary());
+            }
+            Ok(())
+        })
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 21);
+    }
+}
@@ -0,0 +1,96 @@
+//! Encoding of BN254 field elements and points shared by the snarkjs-compatible verifiers
+
+use alloy_primitives::B256;
+use ark_bn254::{Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ec::AffineRepr;
+use ark_ff::{BigInteger, PrimeField, Zero};
+use gadget_sdk::Error;
+
+/// Decode public inputs encoded as consecutive 32-byte big-endian field elements
+pub fn decode_public_inputs(bytes: &[u8]) -> Result<Vec<Fr>, Error> {
+    if bytes.len() % 32 != 0 {
+        return Err(Error::Client(format!(
+            "Public inputs must be 32-byte words, got {} bytes",
+            bytes.len()
+        )));
+    }
+
+    bytes.chunks_exact(32).map(read_field::<Fr>).collect()
+}
+
+/// Public input committing a proof to a 32-byte hash: the hash reduced modulo the BN254 scalar
+/// field
+pub fn hash_input(hash: B256) -> Fr {
+    Fr::from_be_bytes_mod_order(hash.as_slice())
+}
+
+/// Read a canonical big-endian field element, rejecting values not below the modulus
+pub fn read_field<F: PrimeField>(word: &[u8]) -> Result<F, Error> {
+    let value = F::from_be_bytes_mod_order(word);
+    if value.into_bigint().to_bytes_be() != word {
+        return Err(Error::Client(
+            "Field element is not below the field modulus".into(),
+        ));
+    }
+
+    Ok(value)
+}
+
+pub fn parse_field<F: PrimeField>(decimal: &str) -> Result<F, Error> {
+    F::from_str(decimal).map_err(|_| Error::Client(format!("Invalid field element: {}", decimal)))
+}
+
+/// Parse a G1 point given as decimal `[x, y, z]`, where `z` is 1, or 0 for the point at infinity
+pub fn parse_g1(point: &[String; 3]) -> Result<G1Affine, Error> {
+    match point[2].as_str() {
+        "0" => Ok(G1Affine::zero()),
+        "1" => g1(parse_field(&point[0])?, parse_field(&point[1])?),
+        _ => Err(Error::Client("G1 points must be in affine form".into())),
+    }
+}
+
+/// Parse a G2 point given as decimal `[x, y, z]` with coordinates `[c0, c1]`, where `z` is 1, or
+/// 0 for the point at infinity
+pub fn parse_g2(point: &[[String; 2]; 3]) -> Result<G2Affine, Error> {
+    let fq2 = |[c0, c1]: &[String; 2]| -> Result<Fq2, Error> {
+        Ok(Fq2::new(parse_field(c0)?, parse_field(c1)?))
+    };
+
+    let z = fq2(&point[2])?;
+    if z.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+    if z != Fq2::from(1u64) {
+        return Err(Error::Client("G2 points must be in affine form".into()));
+    }
+
+    g2(fq2(&point[0])?, fq2(&point[1])?)
+}
+
+/// Check that `(x, y)` is a point of G1, with `(0, 0)` encoding the point at infinity
+pub fn g1(x: Fq, y: Fq) -> Result<G1Affine, Error> {
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1Affine::zero());
+    }
+
+    let point = G1Affine::new_unchecked(x, y);
+    if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(Error::Client("Invalid G1 point".into()));
+    }
+
+    Ok(point)
+}
+
+/// Check that `(x, y)` is a point of G2, with `(0, 0)` encoding the point at infinity
+pub fn g2(x: Fq2, y: Fq2) -> Result<G2Affine, Error> {
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+
+    let point = G2Affine::new_unchecked(x, y);
+    if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(Error::Client("Invalid G2 point".into()));
+    }
+
+    Ok(point)
+}
@@ -0,0 +1,65 @@
+//! JSON encoding of BN254 field elements and points written by gnark, through Go's
+//! `encoding/json` and gnark-crypto's `MarshalJSON`
+
+use super::bn254::{g1, g2, parse_field};
+use ark_bn254::{Fq2, G1Affine, G2Affine};
+use ark_ff::PrimeField;
+use gadget_sdk::Error;
+use serde::Deserialize;
+
+/// A field element as written by gnark-crypto's `MarshalJSON`: a JSON number when it has at most
+/// 15 digits, a decimal string otherwise
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum GnarkElement {
+    Number(u64),
+    Decimal(String),
+}
+
+impl GnarkElement {
+    pub(super) fn parse<F: PrimeField>(&self) -> Result<F, Error> {
+        match self {
+            Self::Number(value) => Ok(F::from(*value)),
+            Self::Decimal(decimal) => parse_field(decimal),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct GnarkG1 {
+    x: GnarkElement,
+    y: GnarkElement,
+}
+
+impl GnarkG1 {
+    pub(super) fn parse(&self) -> Result<G1Affine, Error> {
+        g1(self.x.parse()?, self.y.parse()?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GnarkE2 {
+    a0: GnarkElement,
+    a1: GnarkElement,
+}
+
+impl GnarkE2 {
+    fn parse(&self) -> Result<Fq2, Error> {
+        Ok(Fq2::new(self.a0.parse()?, self.a1.parse()?))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct GnarkG2 {
+    x: GnarkE2,
+    y: GnarkE2,
+}
+
+impl GnarkG2 {
+    pub(super) fn parse(&self) -> Result<G2Affine, Error> {
+        g2(self.x.parse()?, self.y.parse()?)
+    }
+}
@@ -0,0 +1,411 @@
+//! PLONK verification over BN254 with KZG commitments, for proofs and verifying keys produced by
+//! gnark (v0.10 and later)
+
+use super::bn254::{g1, read_field};
+use super::gnark::{GnarkElement, GnarkG1, GnarkG2};
+use ark_bn254::{Bn254, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{BigInteger, FftField, Field, One, PrimeField, Zero};
+use gadget_sdk::Error;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Size of a proof in the layout written by gnark's `MarshalSolidity`
+pub const PROOF_SIZE: usize = 24 * 32;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GnarkKzgKey {
+    /// `[1]_2` and `[x]_2` of the KZG setup
+    g2: [GnarkG2; 2],
+}
+
+/// Verifying key as written by Go's `encoding/json` for gnark's BN254 `plonk.VerifyingKey`.
+/// Fields the verifier does not need, such as the precomputed pairing lines, are ignored.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GnarkVerifyingKey {
+    size: u64,
+    generator: GnarkElement,
+    nb_public_variables: usize,
+    kzg: GnarkKzgKey,
+    coset_shift: GnarkElement,
+    s: [GnarkG1; 3],
+    ql: GnarkG1,
+    qr: GnarkG1,
+    qm: GnarkG1,
+    qo: GnarkG1,
+    qk: GnarkG1,
+    /// Commitments to the BSB22 commitment selectors, which are not supported
+    #[serde(default)]
+    qcp: Option<Vec<GnarkG1>>,
+}
+
+/// A gnark PLONK proof without BSB22 commitments
+#[derive(Debug, Clone)]
+pub struct GnarkPlonkProof {
+    pub l: G1Affine,
+    pub r: G1Affine,
+    pub o: G1Affine,
+    /// Chunks of the quotient polynomial
+    pub h: [G1Affine; 3],
+    pub l_at_zeta: Fr,
+    pub r_at_zeta: Fr,
+    pub o_at_zeta: Fr,
+    pub s1_at_zeta: Fr,
+    pub s2_at_zeta: Fr,
+    /// The permutation polynomial and its evaluation at `omega * zeta`
+    pub z: G1Affine,
+    pub z_at_zeta_omega: Fr,
+    /// Opening proof of the linearised polynomial, l, r, o, s1 and s2, folded, at zeta
+    pub batch_opening_at_zeta: G1Affine,
+    /// Opening proof of z at `omega * zeta`
+    pub opening_at_zeta_omega: G1Affine,
+}
+
+/// A gnark PLONK verifying key over BN254
+pub struct GnarkPlonkVerifier {
+    size: u64,
+    /// Generator of the evaluation domain
+    generator: Fr,
+    nb_public_variables: usize,
+    /// Shift of the cosets the r and o wires are permuted over
+    coset_shift: Fr,
+    s: [G1Affine; 3],
+    ql: G1Affine,
+    qr: G1Affine,
+    qm: G1Affine,
+    qo: G1Affine,
+    qk: G1Affine,
+    g2: [G2Affine; 2],
+}
+
+/// Fiat-Shamir challenges, derived as by the gnark verifier
+struct Challenges {
+    gamma: Fr,
+    beta: Fr,
+    alpha: Fr,
+    zeta: Fr,
+}
+
+impl GnarkPlonkVerifier {
+    /// Load a verifying key in the JSON encoding of gnark's `plonk.VerifyingKey`
+    pub fn from_json(json: &[u8]) -> Result<Self, Error> {
+        let key: GnarkVerifyingKey = serde_json::from_slice(json)
+            .map_err(|e| Error::Client(format!("Invalid gnark PLONK verifying key: {}", e)))?;
+
+        if key.qcp.is_some_and(|qcp| !qcp.is_empty()) {
+            return Err(Error::Client(
+                "gnark PLONK keys with BSB22 commitments are not supported".into(),
+            ));
+        }
+        if !key.size.is_power_of_two() || key.size.trailing_zeros() > Fr::TWO_ADICITY {
+            return Err(Error::Client(format!(
+                "Invalid evaluation domain size {}",
+                key.size
+            )));
+        }
+
+        // The generator must generate the domain of `size` elements
+        let generator: Fr = key.generator.parse()?;
+        if !generator.pow([key.size]).is_one()
+            || (key.size > 1 && generator.pow([key.size / 2]).is_one())
+        {
+            return Err(Error::Client(format!(
+                "Generator does not generate a domain of {} elements",
+                key.size
+            )));
+        }
+
+        Ok(Self {
+            size: key.size,
+            generator,
+            nb_public_variables: key.nb_public_variables,
+            coset_shift: key.coset_shift.parse()?,
+            s: [key.s[0].parse()?, key.s[1].parse()?, key.s[2].parse()?],
+            ql: key.ql.parse()?,
+            qr: key.qr.parse()?,
+            qm: key.qm.parse()?,
+            qo: key.qo.parse()?,
+            qk: key.qk.parse()?,
+            g2: [key.kzg.g2[0].parse()?, key.kzg.g2[1].parse()?],
+        })
+    }
+
+    pub fn verify(&self, proof: &GnarkPlonkProof, public_inputs: &[Fr]) -> Result<bool, Error> {
+        if public_inputs.len() != self.nb_public_variables {
+            return Err(Error::Client(format!(
+                "Expected {} public inputs, got {}",
+                self.nb_public_variables,
+                public_inputs.len()
+            )));
+        }
+
+        let Challenges {
+            gamma,
+            beta,
+            alpha,
+            zeta,
+        } = self.challenges(proof, public_inputs);
+
+        // Vanishing polynomial and Lagrange basis evaluated at zeta
+        let zeta_n = zeta.pow([self.size]);
+        let zh = zeta_n - Fr::one();
+        let size_inv = Fr::from(self.size)
+            .inverse()
+            .ok_or_else(|| Error::Client("Empty evaluation domain".into()))?;
+        let mut lagrange = Vec::with_capacity(self.nb_public_variables.max(1));
+        let mut root = Fr::one();
+        for _ in 0..self.nb_public_variables.max(1) {
+            let denominator = (zeta - root)
+                .inverse()
+                .ok_or_else(|| Error::Client("Challenge zeta lies in the domain".into()))?;
+            lagrange.push(root * size_inv * zh * denominator);
+            root *= self.generator;
+        }
+        let alpha2_l1 = alpha.square() * lagrange[0];
+
+        let pi = public_inputs
+            .iter()
+            .zip(&lagrange)
+            .fold(Fr::zero(), |pi, (input, l)| pi + *input * l);
+
+        // The MarshalSolidity layout leaves out the opening of the linearised polynomial, which
+        // must be the opposite of the constant part of the relation
+        let (l, r, o) = (proof.l_at_zeta, proof.r_at_zeta, proof.o_at_zeta);
+        let zu = proof.z_at_zeta_omega;
+        let e1 = l + beta * proof.s1_at_zeta + gamma;
+        let e2 = r + beta * proof.s2_at_zeta + gamma;
+        let lin_at_zeta = -(pi - alpha2_l1 + alpha * e1 * e2 * (o + gamma) * zu);
+
+        // Commitment to the linearised polynomial
+        let beta_zeta = beta * zeta;
+        let s3_coefficient = alpha * e1 * e2 * beta * zu;
+        let z_coefficient = alpha2_l1
+            - alpha
+                * (l + beta_zeta + gamma)
+                * (r + beta_zeta * self.coset_shift + gamma)
+                * (o + beta_zeta * self.coset_shift.square() + gamma);
+        let zeta_n2 = zeta_n * zeta.square();
+        let h =
+            G1Projective::from(proof.h[0]) + proof.h[1] * zeta_n2 + proof.h[2] * zeta_n2.square();
+        let lin = (self.ql * l
+            + self.qr * r
+            + self.qm * (l * r)
+            + self.qo * o
+            + self.qk
+            + self.s[2] * s3_coefficient
+            + proof.z * z_coefficient
+            - h * zh)
+            .into_affine();
+
+        // Fold the openings at zeta
+        let digests = [lin, proof.l, proof.r, proof.o, self.s[0], self.s[1]];
+        let claimed_values = [lin_at_zeta, l, r, o, proof.s1_at_zeta, proof.s2_at_zeta];
+        let mut bindings = vec![scalar_bytes(&zeta)];
+        bindings.extend(digests.iter().map(point_bytes));
+        bindings.extend(claimed_values.iter().map(scalar_bytes));
+        bindings.push(scalar_bytes(&zu));
+        let gamma_kzg = Transcript::default().challenge("gamma", &bindings);
+
+        let mut folded_digest = G1Projective::zero();
+        let mut folded_value = Fr::zero();
+        let mut power = Fr::one();
+        for (digest, value) in digests.iter().zip(claimed_values) {
+            folded_digest += *digest * power;
+            folded_value += value * power;
+            power *= gamma_kzg;
+        }
+        let folded_digest = folded_digest.into_affine();
+
+        // Batch the openings at zeta and omega * zeta with a random combination bound to both
+        let mut hasher = Sha256::new();
+        for point in [
+            &folded_digest,
+            &proof.batch_opening_at_zeta,
+            &proof.z,
+            &proof.opening_at_zeta_omega,
+        ] {
+            hasher.update(point_bytes(point));
+        }
+        hasher.update(scalar_bytes(&zeta));
+        hasher.update(scalar_bytes(&gamma_kzg));
+        let lambda = Fr::from_be_bytes_mod_order(&hasher.finalize());
+
+        let zeta_omega = zeta * self.generator;
+        let f = G1Projective::from(folded_digest) + proof.z * lambda
+            - G1Affine::generator() * (folded_value + lambda * zu)
+            + proof.batch_opening_at_zeta * zeta
+            + proof.opening_at_zeta_omega * (lambda * zeta_omega);
+        let quotients = proof.batch_opening_at_zeta + proof.opening_at_zeta_omega * lambda;
+        let pairing = Bn254::multi_pairing(
+            [f.into_affine(), (-quotients).into_affine()],
+            [self.g2[0], self.g2[1]],
+        );
+
+        Ok(pairing.is_zero())
+    }
+
+    fn challenges(&self, proof: &GnarkPlonkProof, public_inputs: &[Fr]) -> Challenges {
+        let mut transcript = Transcript::default();
+
+        let mut bindings: Vec<_> = [
+            &self.s[0], &self.s[1], &self.s[2], &self.ql, &self.qr, &self.qm, &self.qo, &self.qk,
+        ]
+        .into_iter()
+        .map(point_bytes)
+        .collect();
+        bindings.extend(public_inputs.iter().map(scalar_bytes));
+        bindings.extend([&proof.l, &proof.r, &proof.o].into_iter().map(point_bytes));
+        let gamma = transcript.challenge("gamma", &bindings);
+
+        let beta = transcript.challenge("beta", &[]);
+        let alpha = transcript.challenge("alpha", &[point_bytes(&proof.z)]);
+        let zeta =
+            transcript.challenge("zeta", &proof.h.iter().map(point_bytes).collect::<Vec<_>>());
+
+        Challenges {
+            gamma,
+            beta,
+            alpha,
+            zeta,
+        }
+    }
+}
+
+/// SHA-256 transcript matching gnark's `fiat-shamir` package: every challenge hashes its name,
+/// the unreduced previous challenge and its bindings
+#[derive(Default)]
+struct Transcript {
+    previous: Option<[u8; 32]>,
+}
+
+impl Transcript {
+    fn challenge(&mut self, name: &str, bindings: &[Vec<u8>]) -> Fr {
+        let mut hasher = Sha256::new();
+        hasher.update(name.as_bytes());
+        if let Some(previous) = &self.previous {
+            hasher.update(previous);
+        }
+        for binding in bindings {
+            hasher.update(binding);
+        }
+
+        let value: [u8; 32] = hasher.finalize().into();
+        self.previous = Some(value);
+        Fr::from_be_bytes_mod_order(&value)
+    }
+}
+
+/// A point as `x || y`, with the point at infinity as zeros
+fn point_bytes(point: &G1Affine) -> Vec<u8> {
+    match point.xy() {
+        Some((x, y)) => [x.into_bigint().to_bytes_be(), y.into_bigint().to_bytes_be()].concat(),
+        None => vec![0; 64],
+    }
+}
+
+fn scalar_bytes(scalar: &Fr) -> Vec<u8> {
+    scalar.into_bigint().to_bytes_be()
+}
+
+/// Decode a proof in the layout written by gnark's `MarshalSolidity`, every word 32 bytes
+/// big-endian: L, R, O, H0, H1 and H2 as `x || y`, l, r, o, s1 and s2 at zeta, Z, z at
+/// `omega * zeta`, then the opening proofs at zeta and `omega * zeta`
+pub fn decode_proof(proof: &[u8]) -> Result<GnarkPlonkProof, Error> {
+    if proof.len() != PROOF_SIZE {
+        return Err(Error::Client(format!(
+            "gnark PLONK proof must be {} bytes, got {}",
+            PROOF_SIZE,
+            proof.len()
+        )));
+    }
+
+    let words = proof.chunks_exact(32).collect::<Vec<_>>();
+    let point = |i: usize| g1(read_field(words[i])?, read_field(words[i + 1])?);
+    let eval = |i: usize| read_field::<Fr>(words[i]);
+
+    Ok(GnarkPlonkProof {
+        l: point(0)?,
+        r: point(2)?,
+        o: point(4)?,
+        h: [point(6)?, point(8)?, point(10)?],
+        l_at_zeta: eval(12)?,
+        r_at_zeta: eval(13)?,
+        o_at_zeta: eval(14)?,
+        s1_at_zeta: eval(15)?,
+        s2_at_zeta: eval(16)?,
+        z: point(17)?,
+        z_at_zeta_omega: eval(19)?,
+        batch_opening_at_zeta: point(20)?,
+        opening_at_zeta_omega: point(22)?,
+    })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::security::bn254::{decode_public_inputs, parse_field};
+    use crate::security::plonk;
+    use alloy_primitives::hex;
+
+    pub const VERIFICATION_KEY: &str =
+        include_str!("../../fixtures/plonk/gnark/verification_key.json");
+
+    /// The golden proof, in the MarshalSolidity layout
+    pub fn proof_bytes() -> Vec<u8> {
+        hex::decode(include_str!("../../fixtures/plonk/gnark/proof.hex").trim()).unwrap()
+    }
+
+    fn verify(proof: &[u8], public_inputs: &[u8]) -> bool {
+        let verifier = GnarkPlonkVerifier::from_json(VERIFICATION_KEY.as_bytes()).unwrap();
+        verifier
+            .verify(
+                &decode_proof(proof).unwrap(),
+                &decode_public_inputs(public_inputs).unwrap(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_verifies_golden_vector() {
+        // The circuit proves the same statement as the snarkjs golden vector
+        assert!(verify(&proof_bytes(), &plonk::tests::public_input_bytes()));
+    }
+
+    #[test]
+    fn test_rejects_tampered_golden_vector() {
+        let mut inputs = plonk::tests::public_input_bytes();
+        inputs[63] = 16;
+        assert!(!verify(&proof_bytes(), &inputs));
+
+        // z at omega * zeta is word 19
+        let mut proof = proof_bytes();
+        proof[19 * 32 + 31] ^= 1;
+        assert!(!verify(&proof, &plonk::tests::public_input_bytes()));
+
+        // Swap the L and R commitments
+        let mut proof = proof_bytes();
+        let (l, rest) = proof.split_at_mut(64);
+        l.swap_with_slice(&mut rest[..64]);
+        assert!(!verify(&proof, &plonk::tests::public_input_bytes()));
+
+        assert!(decode_proof(&proof_bytes()[1..]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_other_verifying_keys() {
+        assert!(GnarkPlonkVerifier::from_json(plonk::tests::VERIFICATION_KEY.as_bytes()).is_err());
+
+        let mut key: serde_json::Value = serde_json::from_str(VERIFICATION_KEY).unwrap();
+        key["Qcp"] = serde_json::json!([key["Ql"].clone()]);
+        assert!(GnarkPlonkVerifier::from_json(key.to_string().as_bytes()).is_err());
+
+        // A generator of a larger domain than the key's
+        let mut key: serde_json::Value = serde_json::from_str(VERIFICATION_KEY).unwrap();
+        let generator: Fr = parse_field(key["Generator"].as_str().unwrap()).unwrap();
+        key["Generator"] = generator.sqrt().unwrap().into_bigint().to_string().into();
+        assert!(GnarkPlonkVerifier::from_json(key.to_string().as_bytes()).is_err());
+    }
+}
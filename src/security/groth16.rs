@@ -0,0 +1,340 @@
+//! Groth16 verification over BN254, with verifying keys in the snarkjs JSON format or gnark's
+//! JSON encoding
+
+use super::bn254::{g1, g2, parse_g1, parse_g2, read_field};
+use super::gnark::{GnarkG1, GnarkG2};
+use ark_bn254::{Bn254, Fq, Fq2, Fr};
+use ark_groth16::{Groth16, PreparedVerifyingKey, Proof, VerifyingKey};
+use gadget_sdk::Error;
+use serde::Deserialize;
+
+/// Size of an encoded proof: A, B and C with every coordinate a 32-byte big-endian word
+pub const PROOF_SIZE: usize = 8 * 32;
+
+/// Verifying key as written by `snarkjs zkey export verificationkey`. Points are given in
+/// projective coordinates, with the coordinates of G2 points as `[c0, c1]`.
+#[derive(Debug, Deserialize)]
+struct SnarkjsVerifyingKey {
+    protocol: String,
+    curve: String,
+    #[serde(rename = "nPublic")]
+    n_public: usize,
+    vk_alpha_1: [String; 3],
+    vk_beta_2: [[String; 2]; 3],
+    vk_gamma_2: [[String; 2]; 3],
+    vk_delta_2: [[String; 2]; 3],
+    #[serde(rename = "IC")]
+    ic: Vec<[String; 3]>,
+}
+
+/// Verifying key as written by Go's `encoding/json` for gnark's BN254 `groth16.VerifyingKey`.
+/// The G1 `Beta` and `Delta` points, which gnark only keeps for compatibility, are ignored.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GnarkVerifyingKey {
+    g1: GnarkG1Points,
+    g2: GnarkG2Points,
+    /// Wires committed to by each BSB22 commitment, which are not supported
+    #[serde(default)]
+    public_and_commitment_committed: Option<Vec<Vec<usize>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GnarkG1Points {
+    alpha: GnarkG1,
+    /// One point for the constant wire, then one per public input
+    k: Vec<GnarkG1>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GnarkG2Points {
+    beta: GnarkG2,
+    delta: GnarkG2,
+    gamma: GnarkG2,
+}
+
+/// A Groth16 verifying key over BN254, prepared for verification
+pub struct Groth16Verifier {
+    key: PreparedVerifyingKey<Bn254>,
+}
+
+impl Groth16Verifier {
+    /// Load a verifying key in the snarkjs JSON format or the JSON encoding of gnark's
+    /// `groth16.VerifyingKey`, told apart by the `protocol` field only snarkjs writes
+    pub fn from_json(json: &[u8]) -> Result<Self, Error> {
+        let key: serde_json::Value = serde_json::from_slice(json)
+            .map_err(|e| Error::Client(format!("Invalid Groth16 verifying key: {}", e)))?;
+
+        if key.get("protocol").is_some() {
+            Self::from_snarkjs_json(json)
+        } else {
+            Self::from_gnark_json(json)
+        }
+    }
+
+    /// Load a verifying key in the snarkjs JSON format
+    pub fn from_snarkjs_json(json: &[u8]) -> Result<Self, Error> {
+        let key: SnarkjsVerifyingKey = serde_json::from_slice(json)
+            .map_err(|e| Error::Client(format!("Invalid Groth16 verifying key: {}", e)))?;
+
+        if key.protocol != "groth16" {
+            return Err(Error::Client(format!(
+                "Verifying key is for {}, not groth16",
+                key.protocol
+            )));
+        }
+        if key.curve != "bn128" && key.curve != "bn254" {
+            return Err(Error::Client(format!(
+                "Verifying key is over {}, not BN254",
+                key.curve
+            )));
+        }
+        if key.ic.len() != key.n_public + 1 {
+            return Err(Error::Client(format!(
+                "Verifying key has {} IC points for {} public inputs",
+                key.ic.len(),
+                key.n_public
+            )));
+        }
+
+        let key = VerifyingKey {
+            alpha_g1: parse_g1(&key.vk_alpha_1)?,
+            beta_g2: parse_g2(&key.vk_beta_2)?,
+            gamma_g2: parse_g2(&key.vk_gamma_2)?,
+            delta_g2: parse_g2(&key.vk_delta_2)?,
+            gamma_abc_g1: key.ic.iter().map(parse_g1).collect::<Result<_, _>>()?,
+        };
+
+        Ok(Self { key: key.into() })
+    }
+
+    /// Load a verifying key in the JSON encoding of gnark's `groth16.VerifyingKey`. Keys of
+    /// circuits using BSB22 commitments are rejected.
+    pub fn from_gnark_json(json: &[u8]) -> Result<Self, Error> {
+        let key: GnarkVerifyingKey = serde_json::from_slice(json)
+            .map_err(|e| Error::Client(format!("Invalid gnark Groth16 verifying key: {}", e)))?;
+
+        if key
+            .public_and_commitment_committed
+            .is_some_and(|committed| !committed.is_empty())
+        {
+            return Err(Error::Client(
+                "gnark Groth16 keys with BSB22 commitments are not supported".into(),
+            ));
+        }
+        let GnarkVerifyingKey { g1, g2, .. } = key;
+        if g1.k.is_empty() {
+            return Err(Error::Client(
+                "gnark Groth16 verifying key has no K points".into(),
+            ));
+        }
+
+        let key = VerifyingKey {
+            alpha_g1: g1.alpha.parse()?,
+            beta_g2: g2.beta.parse()?,
+            gamma_g2: g2.gamma.parse()?,
+            delta_g2: g2.delta.parse()?,
+            gamma_abc_g1: g1.k.iter().map(GnarkG1::parse).collect::<Result<_, _>>()?,
+        };
+
+        Ok(Self { key: key.into() })
+    }
+
+    pub fn num_public_inputs(&self) -> usize {
+        self.key.vk.gamma_abc_g1.len() - 1
+    }
+
+    pub fn verify(&self, proof: &Proof<Bn254>, public_inputs: &[Fr]) -> Result<bool, Error> {
+        if public_inputs.len() != self.num_public_inputs() {
+            return Err(Error::Client(format!(
+                "Expected {} public inputs, got {}",
+                self.num_public_inputs(),
+                public_inputs.len()
+            )));
+        }
+
+        Groth16::<Bn254>::verify_proof(&self.key, proof, public_inputs)
+            .map_err(|e| Error::Client(format!("Groth16 verification error: {}", e)))
+    }
+}
+
+/// Decode a proof in the layout taken by Solidity verifiers, as produced by
+/// `snarkjs zkey export soliditycalldata` and gnark's `MarshalSolidity`:
+/// A.x || A.y || B.x.c1 || B.x.c0 || B.y.c1 || B.y.c0 || C.x || C.y
+pub fn decode_proof(bytes: &[u8]) -> Result<Proof<Bn254>, Error> {
+    if bytes.len() != PROOF_SIZE {
+        return Err(Error::Client(format!(
+            "Groth16 proof must be {} bytes, got {}",
+            PROOF_SIZE,
+            bytes.len()
+        )));
+    }
+
+    let words = bytes
+        .chunks_exact(32)
+        .map(read_field::<Fq>)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Proof {
+        a: g1(words[0], words[1])?,
+        b: g2(Fq2::new(words[3], words[2]), Fq2::new(words[5], words[4]))?,
+        c: g1(words[6], words[7])?,
+    })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::security::bn254::{decode_public_inputs, hash_input};
+    use alloy_primitives::{hex, B256, U256};
+    use std::str::FromStr;
+
+    pub const VERIFICATION_KEY: &str = include_str!("../../fixtures/groth16/verification_key.json");
+    pub const GNARK_VERIFICATION_KEY: &str =
+        include_str!("../../fixtures/groth16/gnark/verification_key.json");
+
+    #[derive(Deserialize)]
+    struct SnarkjsProof {
+        pi_a: [String; 3],
+        pi_b: [[String; 2]; 3],
+        pi_c: [String; 3],
+    }
+
+    fn word(decimal: &str) -> [u8; 32] {
+        U256::from_str(decimal).unwrap().to_be_bytes()
+    }
+
+    /// Encode a snarkjs `proof.json` in the Solidity calldata layout
+    pub fn encode_proof(json: &str) -> Vec<u8> {
+        let proof: SnarkjsProof = serde_json::from_str(json).unwrap();
+        [
+            &proof.pi_a[0],
+            &proof.pi_a[1],
+            &proof.pi_b[0][1],
+            &proof.pi_b[0][0],
+            &proof.pi_b[1][1],
+            &proof.pi_b[1][0],
+            &proof.pi_c[0],
+            &proof.pi_c[1],
+        ]
+        .into_iter()
+        .flat_map(|coordinate| word(coordinate))
+        .collect()
+    }
+
+    pub fn proof_bytes() -> Vec<u8> {
+        encode_proof(include_str!("../../fixtures/groth16/proof.json"))
+    }
+
+    /// The proof for the gnark key, in the `MarshalSolidity` layout
+    pub fn gnark_proof_bytes() -> Vec<u8> {
+        hex::decode(include_str!("../../fixtures/groth16/gnark/proof.hex").trim()).unwrap()
+    }
+
+    /// The fixture public inputs, shared by the snarkjs and gnark keys: the message ID of `packet_v1.json`'s `case0`, then `9 = 3^2`
+    pub fn public_input_bytes() -> Vec<u8> {
+        let inputs: Vec<String> =
+            serde_json::from_str(include_str!("../../fixtures/groth16/public.json")).unwrap();
+        inputs.iter().flat_map(|input| word(input)).collect()
+    }
+
+    fn verify(public_inputs: &[u8]) -> bool {
+        let verifier = Groth16Verifier::from_snarkjs_json(VERIFICATION_KEY.as_bytes()).unwrap();
+        let proof = decode_proof(&proof_bytes()).unwrap();
+        verifier
+            .verify(&proof, &decode_public_inputs(public_inputs).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_verifies_snarkjs_fixture() {
+        assert!(verify(&public_input_bytes()));
+
+        let mut tampered = public_input_bytes();
+        tampered[63] = 16;
+        assert!(!verify(&tampered));
+    }
+
+    #[test]
+    fn test_verifies_gnark_fixture() {
+        let verifier = Groth16Verifier::from_gnark_json(GNARK_VERIFICATION_KEY.as_bytes()).unwrap();
+        let proof = decode_proof(&gnark_proof_bytes()).unwrap();
+        let inputs = decode_public_inputs(&public_input_bytes()).unwrap();
+        assert!(verifier.verify(&proof, &inputs).unwrap());
+
+        let mut tampered = public_input_bytes();
+        tampered[63] = 16;
+        let tampered = decode_public_inputs(&tampered).unwrap();
+        assert!(!verifier.verify(&proof, &tampered).unwrap());
+
+        // The proofs are not interchangeable between the two keys
+        let snarkjs = decode_proof(&proof_bytes()).unwrap();
+        assert!(!verifier.verify(&snarkjs, &inputs).unwrap());
+    }
+
+    #[test]
+    fn test_detects_key_format() {
+        let proof = decode_proof(&gnark_proof_bytes()).unwrap();
+        let inputs = decode_public_inputs(&public_input_bytes()).unwrap();
+        let gnark = Groth16Verifier::from_json(GNARK_VERIFICATION_KEY.as_bytes()).unwrap();
+        assert!(gnark.verify(&proof, &inputs).unwrap());
+
+        let proof = decode_proof(&proof_bytes()).unwrap();
+        let snarkjs = Groth16Verifier::from_json(VERIFICATION_KEY.as_bytes()).unwrap();
+        assert!(snarkjs.verify(&proof, &inputs).unwrap());
+
+        assert!(Groth16Verifier::from_json(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_message_id_input_matches_fixture() {
+        let message_id: B256 = "0xd0a2a0f0796a2e0a7b4d588acb1e1d46a19cf97936c9addc75eb571e98eabae2"
+            .parse()
+            .unwrap();
+        let inputs = decode_public_inputs(&public_input_bytes()).unwrap();
+        assert_eq!(inputs[0], hash_input(message_id));
+    }
+
+    #[test]
+    fn test_rejects_malformed_proofs() {
+        let proof = proof_bytes();
+        assert!(decode_proof(&proof[..PROOF_SIZE - 1]).is_err());
+
+        // A point off the curve
+        let mut off_curve = proof.clone();
+        off_curve[63] ^= 1;
+        assert!(decode_proof(&off_curve).is_err());
+
+        // A coordinate above the base field modulus
+        let mut non_canonical = proof;
+        non_canonical[..32].fill(0xff);
+        assert!(decode_proof(&non_canonical).is_err());
+
+        let verifier = Groth16Verifier::from_snarkjs_json(VERIFICATION_KEY.as_bytes()).unwrap();
+        let inputs = decode_public_inputs(&public_input_bytes()).unwrap();
+        let proof = decode_proof(&proof_bytes()).unwrap();
+        assert!(verifier.verify(&proof, &inputs[..1]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_other_verifying_keys() {
+        let plonk = VERIFICATION_KEY.replace("\"groth16\"", "\"plonk\"");
+        assert!(Groth16Verifier::from_snarkjs_json(plonk.as_bytes()).is_err());
+
+        let bls = VERIFICATION_KEY.replace("\"bn128\"", "\"bls12381\"");
+        assert!(Groth16Verifier::from_snarkjs_json(bls.as_bytes()).is_err());
+
+        assert!(Groth16Verifier::from_gnark_json(VERIFICATION_KEY.as_bytes()).is_err());
+
+        let mut committed: serde_json::Value =
+            serde_json::from_str(GNARK_VERIFICATION_KEY).unwrap();
+        committed["PublicAndCommitmentCommitted"] = serde_json::json!([[1]]);
+        assert!(Groth16Verifier::from_gnark_json(committed.to_string().as_bytes()).is_err());
+
+        let mut empty: serde_json::Value = serde_json::from_str(GNARK_VERIFICATION_KEY).unwrap();
+        empty["G1"]["K"] = serde_json::json!([]);
+        assert!(Groth16Verifier::from_gnark_json(empty.to_string().as_bytes()).is_err());
+    }
+}
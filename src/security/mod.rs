@@ -1,18 +1,28 @@
 //! Custom security verification implementations for DVN
 
+mod bn254;
+mod gnark;
+mod gnark_plonk;
+mod groth16;
 mod mpc;
 mod oracle;
+mod plonk;
+mod prover;
+mod public_inputs;
 mod signature;
 mod zk_proof;
 
 pub use mpc::MpcVerifier;
 pub use oracle::OracleVerifier;
+pub use prover::{ProverClient, ProverError, Receipt};
+pub use public_inputs::{Encoding, PacketField, PublicInputBinding, PublicInputSchema};
 pub use signature::SignatureVerifier;
 pub use zk_proof::ZkProofVerifier;
 
-use alloy_primitives::{Address, Bytes};
+use alloy_primitives::{Address, Bytes, B256};
 use async_trait::async_trait;
 use gadget_sdk::Error;
+use serde::{Deserialize, Serialize};
 
 /// Type of security verification this DVN performs
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,8 +32,18 @@ pub enum SecurityType {
         threshold: usize,
     },
     ZkProof {
+        /// Verifying key in the format of the proof system's toolchain. For `groth16` this is
+        /// the snarkjs `verification_key.json` or gnark's `groth16.VerifyingKey` as JSON.
         verification_key: Bytes,
         proof_system: String,
+        /// Prover serving proofs at `<prover_url>/<message ID>`, for packets whose DVN options
+        /// carry no proof
+        #[serde(default)]
+        prover_url: Option<String>,
+        /// Public inputs that must commit to the packet, for the `groth16` and `plonk` proof
+        /// systems. Defaults to the first input being the message ID.
+        #[serde(default)]
+        public_inputs: PublicInputSchema,
     },
     Oracle {
         providers: Vec<Address>,
@@ -32,6 +52,10 @@ pub enum SecurityType {
     Mpc {
         participants: Vec<Address>,
         threshold: usize,
+        /// Compressed BLS12-381 public keys of the participants, in the same order, which the
+        /// threshold signature is checked against
+        #[serde(default)]
+        public_keys: Vec<Bytes>,
     },
 }
 
@@ -47,5 +71,13 @@ pub trait SecurityVerifier: Send + Sync {
 pub struct VerificationContext {
     pub chain_id: u64,
     pub verifier_address: Address,
+    /// Message ID of the packet being verified, which proofs must commit to
+    pub message_id: B256,
+    /// Encoded header of the packet being verified
+    pub header: Bytes,
+    /// Payload hash of the packet being verified
+    pub payload_hash: B256,
+    /// Hash of the source chain block the packet was sent in
+    pub source_block_hash: B256,
     pub extra_data: Bytes,
 }
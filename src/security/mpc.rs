@@ -1,9 +1,16 @@
 use super::{SecurityVerifier, VerificationContext};
-use alloy_primitives::{keccak256, Address};
+use crate::options::PacketOptions;
+use alloy_primitives::{keccak256, Address, Bytes, B256};
 use async_trait::async_trait;
+use blst::min_pk::{PublicKey, Signature};
+use blst::BLST_ERROR;
 use gadget_sdk::Error;
 use serde::{Deserialize, Serialize};
 
+/// Domain separation tag of BLS signatures over the result: the proof-of-possession ciphersuite
+/// with signatures in G2, as used by Ethereum consensus
+const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
 /// Multi-Party Computation verification implementation
 /// Currently focuses on verifying threshold signatures from MPC participants
 pub struct MpcVerifier {
@@ -11,6 +18,8 @@ pub struct MpcVerifier {
     participants: Vec<Address>,
     /// Number of required participants
     threshold: usize,
+    /// Compressed BLS12-381 public keys of the participants, in the same order
+    public_keys: Vec<Bytes>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,17 +28,21 @@ struct MpcProof {
     result: Vec<u8>,
     /// Individual participant commitments
     commitments: Vec<Vec<u8>>,
-    /// Proof of correct computation
+    /// Proof of correct computation: an aggregate signature by the same signers on the
+    /// computation transcript of the commitments and result
     computation_proof: Vec<u8>,
     /// Threshold signature on the result
     threshold_signature: Vec<u8>,
+    /// Participants whose signatures were aggregated into the threshold signature
+    signers: Vec<Address>,
 }
 
 impl MpcVerifier {
-    pub fn new(participants: Vec<Address>, threshold: usize) -> Self {
+    pub fn new(participants: Vec<Address>, threshold: usize, public_keys: Vec<Bytes>) -> Self {
         Self {
             participants,
             threshold,
+            public_keys,
         }
     }
 
@@ -47,44 +60,126 @@ impl MpcVerifier {
 
         // 3. Verify the threshold signature
         // This is a single signature that requires t-of-n participants to create
-        self.verify_threshold_signature(&proof.threshold_signature, &proof.result)?;
+        if !self.verify_threshold_signature(
+            &proof.signers,
+            &proof.threshold_signature,
+            &proof.result,
+        )? {
+            return Ok(false);
+        }
 
         // 4. Verify the computation proof
-        // This proves the computation was done correctly according to the MPC protocol
-        self.verify_computation_proof(&proof.computation_proof, &proof.commitments, &proof.result)?;
+        // This proves the signers vouch for the commitments the result was computed from
+        if !self.verify_computation_proof(
+            &proof.signers,
+            &proof.computation_proof,
+            &proof.commitments,
+            &proof.result,
+        )? {
+            return Ok(false);
+        }
 
         Ok(true)
     }
 
-    /// Verify a threshold signature that requires t-of-n participants
-    fn verify_threshold_signature(&self, signature: &[u8], message: &[u8]) -> Result<bool, Error> {
-        // TODO: Implement threshold signature verification
-        // This should verify a single signature that was created by t-of-n participants
-        // Different from individual signatures - this is an aggregated signature
-        unimplemented!("Threshold signature verification not implemented")
+    /// Verify a BLS12-381 aggregate signature on `message` by at least `threshold` distinct
+    /// participants, checked against the group key aggregated from the signers' public keys
+    fn verify_threshold_signature(
+        &self,
+        signers: &[Address],
+        signature: &[u8],
+        message: &[u8],
+    ) -> Result<bool, Error> {
+        if self.public_keys.len() != self.participants.len() {
+            return Err(Error::Client(format!(
+                "Expected a BLS public key for each of the {} MPC participants, got {}",
+                self.participants.len(),
+                self.public_keys.len()
+            )));
+        }
+
+        let mut public_keys = Vec::with_capacity(signers.len());
+        for (i, signer) in signers.iter().enumerate() {
+            if signers[..i].contains(signer) {
+                tracing::warn!("MPC signer {} is listed more than once", signer);
+                return Ok(false);
+            }
+
+            let Some(index) = self.participants.iter().position(|p| p == signer) else {
+                tracing::warn!("MPC signer {} is not a participant", signer);
+                return Ok(false);
+            };
+
+            let public_key = PublicKey::key_validate(&self.public_keys[index]).map_err(|e| {
+                Error::Client(format!("Invalid BLS public key of {}: {:?}", signer, e))
+            })?;
+            public_keys.push(public_key);
+        }
+
+        if public_keys.is_empty() || public_keys.len() < self.threshold {
+            tracing::warn!(
+                "Threshold signature has {} signers, {} required",
+                public_keys.len(),
+                self.threshold
+            );
+            return Ok(false);
+        }
+
+        let signature = Signature::sig_validate(signature, true)
+            .map_err(|e| Error::Client(format!("Invalid threshold signature: {:?}", e)))?;
+        let public_keys: Vec<&PublicKey> = public_keys.iter().collect();
+
+        Ok(
+            signature.fast_aggregate_verify(true, message, BLS_DST, &public_keys)
+                == BLST_ERROR::BLST_SUCCESS,
+        )
     }
 
-    /// Verify the proof of correct MPC computation
+    /// Verify the proof of correct MPC computation: every signer published a commitment, and the
+    /// signers jointly signed the transcript binding those commitments to the result
     fn verify_computation_proof(
         &self,
+        signers: &[Address],
         proof: &[u8],
         commitments: &[Vec<u8>],
         result: &[u8],
     ) -> Result<bool, Error> {
-        // TODO: Implement MPC computation verification
-        // This should verify:
-        // 1. All participant commitments are valid
-        // 2. The computation was performed correctly
-        // 3. The result is consistent with the commitments
-        unimplemented!("MPC computation proof verification not implemented")
+        if commitments.len() != signers.len() || commitments.iter().any(Vec::is_empty) {
+            tracing::warn!(
+                "MPC proof has {} commitments for {} signers",
+                commitments.iter().filter(|c| !c.is_empty()).count(),
+                signers.len()
+            );
+            return Ok(false);
+        }
+
+        let transcript = computation_transcript(commitments, result);
+        self.verify_threshold_signature(signers, proof, transcript.as_slice())
+    }
+}
+
+/// Hash of the commitments, each prefixed with its 4-byte big-endian length, followed by the result
+fn computation_transcript(commitments: &[Vec<u8>], result: &[u8]) -> B256 {
+    let mut transcript = Vec::new();
+    for commitment in commitments {
+        transcript.extend_from_slice(&(commitment.len() as u32).to_be_bytes());
+        transcript.extend_from_slice(commitment);
     }
+    transcript.extend_from_slice(result);
+    keccak256(transcript)
 }
 
 #[async_trait]
 impl SecurityVerifier for MpcVerifier {
     async fn verify(&self, data: &[u8], context: &VerificationContext) -> Result<bool, Error> {
-        // Decode MPC proof from context.extra_data
-        let proof: MpcProof = serde_json::from_slice(&context.extra_data)
+        // The JSON-encoded proof is carried in the params of the first DVN option addressed to us
+        let proof_data = PacketOptions::decode(&context.extra_data)?
+            .dvn
+            .into_iter()
+            .next()
+            .map(|option| option.params)
+            .ok_or_else(|| Error::Client("No MPC proof in the DVN options".into()))?;
+        let proof: MpcProof = serde_json::from_slice(&proof_data)
             .map_err(|e| Error::Client(format!("Failed to decode MPC proof: {}", e)))?;
 
         self.verify_computation(&proof, data)
@@ -94,12 +189,158 @@ impl SecurityVerifier for MpcVerifier {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::options::DvnOption;
+    use blst::min_pk::{AggregateSignature, SecretKey};
+
+    const RESULT: &[u8] = b"verified packet";
+
+    fn secret_key(i: u8) -> SecretKey {
+        SecretKey::key_gen(&[i; 32], &[]).unwrap()
+    }
+
+    fn participant(i: u8) -> Address {
+        Address::repeat_byte(i + 1)
+    }
+
+    /// A 2-of-3 verifier
+    fn verifier() -> MpcVerifier {
+        MpcVerifier::new(
+            (0..3).map(participant).collect(),
+            2,
+            (0..3)
+                .map(|i| Bytes::from(secret_key(i).sk_to_pk().compress().to_vec()))
+                .collect(),
+        )
+    }
+
+    fn sign(signers: &[u8], message: &[u8]) -> Vec<u8> {
+        let signatures: Vec<Signature> = signers
+            .iter()
+            .map(|&i| secret_key(i).sign(message, BLS_DST, &[]))
+            .collect();
+        let signatures: Vec<&Signature> = signatures.iter().collect();
+        AggregateSignature::aggregate(&signatures, true)
+            .unwrap()
+            .to_signature()
+            .compress()
+            .to_vec()
+    }
+
+    fn verify(signers: &[u8], signature: &[u8]) -> bool {
+        let signers: Vec<Address> = signers.iter().map(|&i| participant(i)).collect();
+        verifier()
+            .verify_threshold_signature(&signers, signature, RESULT)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_threshold_signature_verification() {
+        assert!(verify(&[0, 2], &sign(&[0, 2], RESULT)));
+        assert!(verify(&[0, 1, 2], &sign(&[0, 1, 2], RESULT)));
+
+        // Signed by other participants than claimed, or over another message
+        assert!(!verify(&[0, 1], &sign(&[0, 2], RESULT)));
+        assert!(!verify(&[0, 2], &sign(&[0, 2], b"other packet")));
+
+        // Too few, repeated or unknown signers
+        assert!(!verify(&[1], &sign(&[1], RESULT)));
+        assert!(!verify(&[1, 1], &sign(&[1, 1], RESULT)));
+        assert!(!verify(&[0, 3], &sign(&[0, 3], RESULT)));
+    }
+
+    #[test]
+    fn test_rejects_misconfigured_public_keys() {
+        let verifier = MpcVerifier::new((0..3).map(participant).collect(), 2, Vec::new());
+        assert!(verifier
+            .verify_threshold_signature(
+                &[participant(0), participant(1)],
+                &sign(&[0, 1], RESULT),
+                RESULT
+            )
+            .is_err());
+    }
+
+    /// A proof of `RESULT` by participants 0 and 1, with each of them committing to a share
+    fn valid_proof() -> MpcProof {
+        let commitments = vec![b"share 0".to_vec(), b"share 1".to_vec()];
+        let transcript = computation_transcript(&commitments, RESULT);
+        MpcProof {
+            result: RESULT.to_vec(),
+            computation_proof: sign(&[0, 1], transcript.as_slice()),
+            commitments,
+            threshold_signature: sign(&[0, 1], RESULT),
+            signers: vec![participant(0), participant(1)],
+        }
+    }
+
+    fn context(proof: &MpcProof) -> VerificationContext {
+        let options = PacketOptions {
+            executor: Vec::new(),
+            dvn: vec![DvnOption {
+                index: 0,
+                option_type: 1,
+                params: serde_json::to_vec(proof).unwrap().into(),
+            }],
+        };
+
+        VerificationContext {
+            chain_id: 1,
+            verifier_address: Address::ZERO,
+            message_id: Default::default(),
+            header: Bytes::new(),
+            payload_hash: Default::default(),
+            source_block_hash: Default::default(),
+            extra_data: options.encode_dvn_options(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_valid_proof_passes_verification() {
+        assert!(verifier()
+            .verify(RESULT, &context(&valid_proof()))
+            .await
+            .unwrap());
+
+        // The proof only vouches for the result it was made for
+        assert!(!verifier()
+            .verify(b"other packet", &context(&valid_proof()))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_failed_signature_fails_verification() {
+        let proof = MpcProof {
+            threshold_signature: sign(&[0, 1], b"other packet"),
+            ..valid_proof()
+        };
+
+        assert!(!verifier().verify(RESULT, &context(&proof)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_failed_computation_proof_fails_verification() {
+        // Commitments other than the ones the signers vouched for
+        let proof = MpcProof {
+            commitments: vec![b"share 0".to_vec(), b"forged share".to_vec()],
+            ..valid_proof()
+        };
+        assert!(!verifier().verify(RESULT, &context(&proof)).await.unwrap());
+
+        // A signer without a commitment
+        let proof = MpcProof {
+            commitments: vec![b"share 0".to_vec()],
+            ..valid_proof()
+        };
+        assert!(!verifier().verify(RESULT, &context(&proof)).await.unwrap());
+    }
 
     #[tokio::test]
-    async fn test_mpc_verification() {
-        // TODO: Add tests for:
-        // 1. Threshold signature verification
-        // 2. MPC computation proof verification
-        // 3. Full MPC verification flow
+    async fn test_requires_proof_in_dvn_options() {
+        let context = VerificationContext {
+            extra_data: Bytes::new(),
+            ..context(&valid_proof())
+        };
+        assert!(verifier().verify(RESULT, &context).await.is_err());
     }
 }
@@ -0,0 +1,514 @@
+//! PLONK verification over BN254 with KZG commitments, for proofs and verifying keys in the
+//! snarkjs format. Proofs start with a serialization version naming the toolchain that produced
+//! them; gnark proofs are verified by [`super::gnark_plonk`].
+
+use super::bn254::{g1, parse_field, parse_g1, parse_g2, read_field};
+use super::gnark_plonk::{self, GnarkPlonkProof};
+use alloy_primitives::keccak256;
+use ark_bn254::{Bn254, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{BigInteger, FftField, Field, One, PrimeField, Zero};
+use gadget_sdk::Error;
+use serde::Deserialize;
+
+/// Serialization version 1: a snarkjs PLONK proof (snarkjs 0.7 and later) with its Keccak-256
+/// transcript, in the 24-word layout taken by the snarkjs Solidity verifier:
+/// A, B, C, Z, T1, T2, T3, Wxi, Wxiw as `x || y`, then eval_a, eval_b, eval_c, eval_s1, eval_s2
+/// and eval_zw, every word 32 bytes big-endian
+pub const SERIALIZATION_V1: u8 = 1;
+
+/// Serialization version 2: a gnark PLONK proof (gnark 0.10 and later) without BSB22
+/// commitments, with its SHA-256 transcript, in the 24-word layout written by `MarshalSolidity`
+pub const SERIALIZATION_V2: u8 = 2;
+
+/// Serialization versions accepted by [`decode_proof`]. halo2 proofs are not accepted: their
+/// verifying keys do not carry the circuit's constraint system, which verification needs.
+pub const SUPPORTED_VERSIONS: &[u8] = &[SERIALIZATION_V1, SERIALIZATION_V2];
+
+/// Size of a version 1 proof, without its version byte
+const PROOF_V1_SIZE: usize = 24 * 32;
+
+/// Verifying key as written by `snarkjs zkey export verificationkey`
+#[derive(Debug, Deserialize)]
+struct SnarkjsVerifyingKey {
+    protocol: String,
+    curve: String,
+    #[serde(rename = "nPublic")]
+    n_public: usize,
+    power: u32,
+    k1: String,
+    k2: String,
+    #[serde(rename = "Qm")]
+    qm: [String; 3],
+    #[serde(rename = "Ql")]
+    ql: [String; 3],
+    #[serde(rename = "Qr")]
+    qr: [String; 3],
+    #[serde(rename = "Qo")]
+    qo: [String; 3],
+    #[serde(rename = "Qc")]
+    qc: [String; 3],
+    #[serde(rename = "S1")]
+    s1: [String; 3],
+    #[serde(rename = "S2")]
+    s2: [String; 3],
+    #[serde(rename = "S3")]
+    s3: [String; 3],
+    #[serde(rename = "X_2")]
+    x_2: [[String; 2]; 3],
+    w: String,
+}
+
+/// A PLONK proof: commitments to the wire, permutation and quotient polynomials, their opening
+/// proofs and the evaluations they open to
+#[derive(Debug, Clone)]
+pub struct PlonkProof {
+    pub a: G1Affine,
+    pub b: G1Affine,
+    pub c: G1Affine,
+    pub z: G1Affine,
+    pub t1: G1Affine,
+    pub t2: G1Affine,
+    pub t3: G1Affine,
+    pub wxi: G1Affine,
+    pub wxiw: G1Affine,
+    pub eval_a: Fr,
+    pub eval_b: Fr,
+    pub eval_c: Fr,
+    pub eval_s1: Fr,
+    pub eval_s2: Fr,
+    pub eval_zw: Fr,
+}
+
+/// A proof decoded according to its serialization version
+#[derive(Debug, Clone)]
+pub enum VersionedProof {
+    Snarkjs(PlonkProof),
+    Gnark(GnarkPlonkProof),
+}
+
+/// A PLONK verifying key over BN254
+pub struct PlonkVerifier {
+    n_public: usize,
+    power: u32,
+    k1: Fr,
+    k2: Fr,
+    qm: G1Affine,
+    ql: G1Affine,
+    qr: G1Affine,
+    qo: G1Affine,
+    qc: G1Affine,
+    s1: G1Affine,
+    s2: G1Affine,
+    s3: G1Affine,
+    /// `[x]_2` of the KZG setup
+    x_2: G2Affine,
+    /// Generator of the evaluation domain
+    w: Fr,
+}
+
+/// Fiat-Shamir challenges, derived as by the snarkjs verifier
+struct Challenges {
+    beta: Fr,
+    gamma: Fr,
+    alpha: Fr,
+    xi: Fr,
+    v: [Fr; 5],
+    u: Fr,
+}
+
+impl PlonkVerifier {
+    /// Load a verifying key in the snarkjs JSON format
+    pub fn from_snarkjs_json(json: &[u8]) -> Result<Self, Error> {
+        let key: SnarkjsVerifyingKey = serde_json::from_slice(json)
+            .map_err(|e| Error::Client(format!("Invalid PLONK verifying key: {}", e)))?;
+
+        if key.protocol != "plonk" {
+            return Err(Error::Client(format!(
+                "Verifying key is for {}, not plonk",
+                key.protocol
+            )));
+        }
+        if key.curve != "bn128" && key.curve != "bn254" {
+            return Err(Error::Client(format!(
+                "Verifying key is over {}, not BN254",
+                key.curve
+            )));
+        }
+        if key.power > Fr::TWO_ADICITY {
+            return Err(Error::Client(format!(
+                "Domain of 2^{} exceeds the BN254 scalar field",
+                key.power
+            )));
+        }
+
+        // `w` must generate the domain of 2^power elements
+        let w: Fr = parse_field(&key.w)?;
+        let n = 1u64 << key.power;
+        if !w.pow([n]).is_one() || (key.power > 0 && w.pow([n / 2]).is_one()) {
+            return Err(Error::Client(format!(
+                "w does not generate a domain of 2^{} elements",
+                key.power
+            )));
+        }
+
+        Ok(Self {
+            n_public: key.n_public,
+            power: key.power,
+            k1: parse_field(&key.k1)?,
+            k2: parse_field(&key.k2)?,
+            qm: parse_g1(&key.qm)?,
+            ql: parse_g1(&key.ql)?,
+            qr: parse_g1(&key.qr)?,
+            qo: parse_g1(&key.qo)?,
+            qc: parse_g1(&key.qc)?,
+            s1: parse_g1(&key.s1)?,
+            s2: parse_g1(&key.s2)?,
+            s3: parse_g1(&key.s3)?,
+            x_2: parse_g2(&key.x_2)?,
+            w,
+        })
+    }
+
+    pub fn num_public_inputs(&self) -> usize {
+        self.n_public
+    }
+
+    pub fn verify(&self, proof: &PlonkProof, public_inputs: &[Fr]) -> Result<bool, Error> {
+        if public_inputs.len() != self.n_public {
+            return Err(Error::Client(format!(
+                "Expected {} public inputs, got {}",
+                self.n_public,
+                public_inputs.len()
+            )));
+        }
+
+        let Challenges {
+            beta,
+            gamma,
+            alpha,
+            xi,
+            v,
+            u,
+        } = self.challenges(proof, public_inputs);
+
+        // Vanishing polynomial and Lagrange basis evaluated at xi
+        let n = 1u64 << self.power;
+        let xin = xi.pow([n]);
+        let zh = xin - Fr::one();
+        let mut lagrange = Vec::with_capacity(self.n_public.max(1));
+        let mut root = Fr::one();
+        for _ in 0..self.n_public.max(1) {
+            let denominator = (Fr::from(n) * (xi - root))
+                .inverse()
+                .ok_or_else(|| Error::Client("Challenge xi lies in the domain".into()))?;
+            lagrange.push(root * zh * denominator);
+            root *= self.w;
+        }
+        let l1 = lagrange[0];
+
+        let pi = public_inputs
+            .iter()
+            .zip(&lagrange)
+            .fold(Fr::zero(), |pi, (input, l)| pi - *input * l);
+
+        let alpha2 = alpha.square();
+        let e3a = proof.eval_a + beta * proof.eval_s1 + gamma;
+        let e3b = proof.eval_b + beta * proof.eval_s2 + gamma;
+        let r0 = pi - l1 * alpha2 - e3a * e3b * (proof.eval_c + gamma) * proof.eval_zw * alpha;
+
+        // Commitment to the linearization polynomial, plus u times the permutation commitment
+        let betaxi = beta * xi;
+        let d2 = (proof.eval_a + betaxi + gamma)
+            * (proof.eval_b + betaxi * self.k1 + gamma)
+            * (proof.eval_c + betaxi * self.k2 + gamma)
+            * alpha
+            + l1 * alpha2
+            + u;
+        let d3 = e3a * e3b * alpha * beta * proof.eval_zw;
+        let d4 = (G1Projective::from(proof.t1) + proof.t2 * xin + proof.t3 * xin.square()) * zh;
+        let d = self.qm * (proof.eval_a * proof.eval_b)
+            + self.ql * proof.eval_a
+            + self.qr * proof.eval_b
+            + self.qo * proof.eval_c
+            + self.qc
+            + proof.z * d2
+            - self.s3 * d3
+            - d4;
+
+        let f =
+            d + proof.a * v[0] + proof.b * v[1] + proof.c * v[2] + self.s1 * v[3] + self.s2 * v[4];
+        let e = G1Affine::generator()
+            * (-r0
+                + v[0] * proof.eval_a
+                + v[1] * proof.eval_b
+                + v[2] * proof.eval_c
+                + v[3] * proof.eval_s1
+                + v[4] * proof.eval_s2
+                + u * proof.eval_zw);
+
+        // Batched KZG opening at xi and xi * w
+        let a1 = proof.wxi + proof.wxiw * u;
+        let b1 = proof.wxi * xi + proof.wxiw * (u * xi * self.w) + f - e;
+        let pairing = Bn254::multi_pairing(
+            [(-a1).into_affine(), b1.into_affine()],
+            [self.x_2, G2Affine::generator()],
+        );
+
+        Ok(pairing.is_zero())
+    }
+
+    fn challenges(&self, proof: &PlonkProof, public_inputs: &[Fr]) -> Challenges {
+        let mut transcript = Transcript::default();
+
+        for point in [
+            &self.qm, &self.ql, &self.qr, &self.qo, &self.qc, &self.s1, &self.s2, &self.s3,
+        ] {
+            transcript.point(point);
+        }
+        for input in public_inputs {
+            transcript.scalar(input);
+        }
+        for point in [&proof.a, &proof.b, &proof.c] {
+            transcript.point(point);
+        }
+        let beta = transcript.challenge();
+
+        transcript.scalar(&beta);
+        let gamma = transcript.challenge();
+
+        transcript.scalar(&beta);
+        transcript.scalar(&gamma);
+        transcript.point(&proof.z);
+        let alpha = transcript.challenge();
+
+        transcript.scalar(&alpha);
+        for point in [&proof.t1, &proof.t2, &proof.t3] {
+            transcript.point(point);
+        }
+        let xi = transcript.challenge();
+
+        transcript.scalar(&xi);
+        for eval in [
+            &proof.eval_a,
+            &proof.eval_b,
+            &proof.eval_c,
+            &proof.eval_s1,
+            &proof.eval_s2,
+            &proof.eval_zw,
+        ] {
+            transcript.scalar(eval);
+        }
+        let v1 = transcript.challenge();
+        let mut v = [v1; 5];
+        for i in 1..5 {
+            v[i] = v[i - 1] * v1;
+        }
+
+        transcript.point(&proof.wxi);
+        transcript.point(&proof.wxiw);
+        let u = transcript.challenge();
+
+        Challenges {
+            beta,
+            gamma,
+            alpha,
+            xi,
+            v,
+            u,
+        }
+    }
+}
+
+/// Keccak-256 transcript matching snarkjs: points are absorbed as `x || y` and scalars as
+/// 32-byte big-endian words, and every challenge hashes what was absorbed since the last one
+#[derive(Default)]
+struct Transcript(Vec<u8>);
+
+impl Transcript {
+    fn point(&mut self, point: &G1Affine) {
+        match point.xy() {
+            Some((x, y)) => {
+                self.0.extend_from_slice(&x.into_bigint().to_bytes_be());
+                self.0.extend_from_slice(&y.into_bigint().to_bytes_be());
+            }
+            None => self.0.extend_from_slice(&[0; 64]),
+        }
+    }
+
+    fn scalar(&mut self, scalar: &Fr) {
+        self.0
+            .extend_from_slice(&scalar.into_bigint().to_bytes_be());
+    }
+
+    fn challenge(&mut self) -> Fr {
+        let challenge = Fr::from_be_bytes_mod_order(keccak256(&self.0).as_slice());
+        self.0.clear();
+        challenge
+    }
+}
+
+/// Decode a proof made of a serialization version byte followed by the proof in that version's
+/// layout
+pub fn decode_proof(bytes: &[u8]) -> Result<VersionedProof, Error> {
+    match bytes.split_first() {
+        Some((&SERIALIZATION_V1, proof)) => {
+            decode_snarkjs_proof(proof).map(VersionedProof::Snarkjs)
+        }
+        Some((&SERIALIZATION_V2, proof)) => {
+            gnark_plonk::decode_proof(proof).map(VersionedProof::Gnark)
+        }
+        Some((version, _)) => Err(Error::Client(format!(
+            "Unsupported PLONK proof serialization version {}, expected one of {:?}",
+            version, SUPPORTED_VERSIONS
+        ))),
+        None => Err(Error::Client("Empty PLONK proof".into())),
+    }
+}
+
+fn decode_snarkjs_proof(proof: &[u8]) -> Result<PlonkProof, Error> {
+    if proof.len() != PROOF_V1_SIZE {
+        return Err(Error::Client(format!(
+            "PLONK proof must be {} bytes, got {}",
+            PROOF_V1_SIZE,
+            proof.len()
+        )));
+    }
+
+    let words = proof.chunks_exact(32).collect::<Vec<_>>();
+    let point = |i: usize| g1(read_field(words[i])?, read_field(words[i + 1])?);
+    let eval = |i: usize| read_field::<Fr>(words[i]);
+
+    Ok(PlonkProof {
+        a: point(0)?,
+        b: point(2)?,
+        c: point(4)?,
+        z: point(6)?,
+        t1: point(8)?,
+        t2: point(10)?,
+        t3: point(12)?,
+        wxi: point(14)?,
+        wxiw: point(16)?,
+        eval_a: eval(18)?,
+        eval_b: eval(19)?,
+        eval_c: eval(20)?,
+        eval_s1: eval(21)?,
+        eval_s2: eval(22)?,
+        eval_zw: eval(23)?,
+    })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::security::bn254::{decode_public_inputs, hash_input};
+    use alloy_primitives::{B256, U256};
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    pub const VERIFICATION_KEY: &str = include_str!("../../fixtures/plonk/verification_key.json");
+
+    fn word(decimal: &str) -> [u8; 32] {
+        U256::from_str(decimal).unwrap().to_be_bytes()
+    }
+
+    /// The golden proof, as a version 1 proof
+    pub fn proof_bytes() -> Vec<u8> {
+        let proof: BTreeMap<String, serde_json::Value> =
+            serde_json::from_str(include_str!("../../fixtures/plonk/proof.json")).unwrap();
+        let coordinate = |name: &str, i: usize| word(proof[name][i].as_str().unwrap());
+        let eval = |name: &str| word(proof[name].as_str().unwrap());
+
+        let mut bytes = vec![SERIALIZATION_V1];
+        for name in ["A", "B", "C", "Z", "T1", "T2", "T3", "Wxi", "Wxiw"] {
+            bytes.extend_from_slice(&coordinate(name, 0));
+            bytes.extend_from_slice(&coordinate(name, 1));
+        }
+        for name in [
+            "eval_a", "eval_b", "eval_c", "eval_s1", "eval_s2", "eval_zw",
+        ] {
+            bytes.extend_from_slice(&eval(name));
+        }
+        bytes
+    }
+
+    /// The golden public inputs: the message ID of `packet_v1.json`'s `case0`, then `9 = 3^2`
+    pub fn public_input_bytes() -> Vec<u8> {
+        let inputs: Vec<String> =
+            serde_json::from_str(include_str!("../../fixtures/plonk/public.json")).unwrap();
+        inputs.iter().flat_map(|input| word(input)).collect()
+    }
+
+    fn verify(proof: &[u8], public_inputs: &[u8]) -> bool {
+        let verifier = PlonkVerifier::from_snarkjs_json(VERIFICATION_KEY.as_bytes()).unwrap();
+        let VersionedProof::Snarkjs(proof) = decode_proof(proof).unwrap() else {
+            panic!("Not a snarkjs proof");
+        };
+        verifier
+            .verify(&proof, &decode_public_inputs(public_inputs).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_verifies_golden_vector() {
+        assert!(verify(&proof_bytes(), &public_input_bytes()));
+
+        let message_id: B256 = "0xd0a2a0f0796a2e0a7b4d588acb1e1d46a19cf97936c9addc75eb571e98eabae2"
+            .parse()
+            .unwrap();
+        let inputs = decode_public_inputs(&public_input_bytes()).unwrap();
+        assert_eq!(inputs[0], hash_input(message_id));
+    }
+
+    #[test]
+    fn test_rejects_tampered_golden_vector() {
+        let mut inputs = public_input_bytes();
+        inputs[63] = 16;
+        assert!(!verify(&proof_bytes(), &inputs));
+
+        // eval_zw is the last word of the proof
+        let mut proof = proof_bytes();
+        *proof.last_mut().unwrap() ^= 1;
+        assert!(!verify(&proof, &public_input_bytes()));
+
+        // Swap the A and B commitments
+        let mut proof = proof_bytes();
+        let (a, rest) = proof[1..].split_at_mut(64);
+        a.swap_with_slice(&mut rest[..64]);
+        assert!(!verify(&proof, &public_input_bytes()));
+    }
+
+    #[test]
+    fn test_decodes_by_serialization_version() {
+        let mut proof = proof_bytes();
+        proof[0] = 3;
+        assert!(decode_proof(&proof).is_err());
+        assert!(decode_proof(&[]).is_err());
+        assert!(decode_proof(&proof_bytes()[..PROOF_V1_SIZE]).is_err());
+
+        let mut gnark = vec![SERIALIZATION_V2];
+        gnark.extend_from_slice(&gnark_plonk::tests::proof_bytes());
+        assert!(matches!(
+            decode_proof(&gnark).unwrap(),
+            VersionedProof::Gnark(_)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_other_verifying_keys() {
+        let groth16 = VERIFICATION_KEY.replace("\"plonk\"", "\"groth16\"");
+        assert!(PlonkVerifier::from_snarkjs_json(groth16.as_bytes()).is_err());
+        let gnark = gnark_plonk::tests::VERIFICATION_KEY;
+        assert!(PlonkVerifier::from_snarkjs_json(gnark.as_bytes()).is_err());
+
+        // A generator of a larger domain than the key's
+        let key: serde_json::Value = serde_json::from_str(VERIFICATION_KEY).unwrap();
+        let w: Fr = parse_field(key["w"].as_str().unwrap()).unwrap();
+        let wrong_w = VERIFICATION_KEY.replace(
+            key["w"].as_str().unwrap(),
+            &w.sqrt().unwrap().into_bigint().to_string(),
+        );
+        assert!(PlonkVerifier::from_snarkjs_json(wrong_w.as_bytes()).is_err());
+    }
+}
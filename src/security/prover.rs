@@ -0,0 +1,119 @@
+//! Retrieval of proofs from an external prover, for packets whose DVN options carry no proof,
+//! such as proofs only produced after the packet was sent
+
+use alloy_primitives::{Bytes, B256};
+use alloy_transport_http::reqwest::{self, StatusCode};
+use gadget_sdk::Error;
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+
+/// How long to wait for the prover to answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A proof of a packet served by the prover, with the public inputs or committed public values
+/// it proves
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Receipt {
+    pub proof: Bytes,
+    pub public_inputs: Bytes,
+}
+
+/// Reasons a receipt could not be fetched
+#[derive(Debug)]
+pub enum ProverError {
+    /// The prover has no receipt for the message yet
+    NotReady,
+    /// The prover could not be reached or failed to answer
+    Transport(String),
+    /// The prover answered with something other than a receipt
+    Invalid(String),
+}
+
+impl fmt::Display for ProverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotReady => write!(f, "Receipt not available from the prover yet"),
+            Self::Transport(reason) => write!(f, "Failed to fetch receipt: {}", reason),
+            Self::Invalid(reason) => write!(f, "Invalid receipt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProverError {}
+
+impl From<ProverError> for Error {
+    fn from(e: ProverError) -> Self {
+        Error::Client(e.to_string())
+    }
+}
+
+/// Client of a prover serving receipts as JSON at `<url>/<message ID>`
+pub struct ProverClient {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl ProverClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Fetch the receipt for `message_id`
+    pub async fn receipt(&self, message_id: B256) -> Result<Receipt, ProverError> {
+        let response = self
+            .client
+            .get(receipt_url(&self.url, message_id))
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| ProverError::Transport(e.to_string()))?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => return Err(ProverError::NotReady),
+            status if !status.is_success() => {
+                return Err(ProverError::Transport(format!(
+                    "Prover answered {}",
+                    status
+                )))
+            }
+            _ => {}
+        }
+
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| ProverError::Transport(e.to_string()))?;
+        serde_json::from_slice(&body).map_err(|e| ProverError::Invalid(e.to_string()))
+    }
+}
+
+fn receipt_url(url: &str, message_id: B256) -> String {
+    format!("{}/{}", url, message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receipt_url() {
+        let message_id = B256::repeat_byte(0xab);
+        let client = ProverClient::new("https://prover.example/receipts/");
+        assert_eq!(
+            receipt_url(&client.url, message_id),
+            format!("https://prover.example/receipts/0x{}", "ab".repeat(32))
+        );
+    }
+
+    #[test]
+    fn test_decodes_receipt() {
+        let receipt: Receipt =
+            serde_json::from_str(r#"{"proof": "0x0102", "public_inputs": "0x03"}"#).unwrap();
+        assert_eq!(receipt.proof, Bytes::from_static(&[1, 2]));
+        assert_eq!(receipt.public_inputs, Bytes::from_static(&[3]));
+    }
+}
@@ -0,0 +1,337 @@
+//! Declarative binding of ZK proof public inputs to the packet being verified
+
+use super::bn254;
+use super::VerificationContext;
+use crate::codec::PacketHeader;
+use alloy_primitives::B256;
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use gadget_sdk::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A property of the packet being verified that a public input can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PacketField {
+    SrcEid,
+    DstEid,
+    Nonce,
+    PayloadHash,
+    /// Hash of the source chain block containing the `PacketSent` log
+    SourceBlockHash,
+    MessageId,
+}
+
+/// How a 32-byte field is encoded as a BN254 scalar. Integer fields are always encoded as
+/// themselves and only accept [`Encoding::Reduced`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// The big-endian value reduced modulo the scalar field
+    #[default]
+    Reduced,
+    /// The high 128 bits
+    High,
+    /// The low 128 bits
+    Low,
+}
+
+/// Requirement that the public input at `index` equals `field` of the packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicInputBinding {
+    pub index: usize,
+    pub field: PacketField,
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+/// The public inputs a proof must commit to. Inputs not named by a binding are left to the
+/// circuit. The default binds the first input to the message ID.
+///
+/// A schema loaded from the config must bind the message ID, or both the payload hash and the
+/// nonce, so a proof cannot be replayed for another packet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<PublicInputBinding>")]
+pub struct PublicInputSchema(pub Vec<PublicInputBinding>);
+
+impl TryFrom<Vec<PublicInputBinding>> for PublicInputSchema {
+    type Error = Error;
+
+    fn try_from(bindings: Vec<PublicInputBinding>) -> Result<Self, Error> {
+        let schema = Self(bindings);
+        if !schema.binds(PacketField::MessageId)
+            && !(schema.binds(PacketField::PayloadHash) && schema.binds(PacketField::Nonce))
+        {
+            return Err(Error::Client(
+                "Public inputs must bind message_id, or both payload_hash and nonce".into(),
+            ));
+        }
+
+        Ok(schema)
+    }
+}
+
+impl Default for PublicInputSchema {
+    fn default() -> Self {
+        Self(vec![PublicInputBinding {
+            index: 0,
+            field: PacketField::MessageId,
+            encoding: Encoding::Reduced,
+        }])
+    }
+}
+
+/// A public input that does not commit to the packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputMismatch {
+    pub index: usize,
+    pub field: PacketField,
+    /// The input, or `None` if the proof has too few public inputs
+    pub input: Option<Fr>,
+    pub expected: Fr,
+}
+
+impl fmt::Display for InputMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.input {
+            Some(input) => write!(
+                f,
+                "input {} is {}, expected {:?} {}",
+                self.index, input, self.field, self.expected
+            ),
+            None => write!(
+                f,
+                "input {} is missing, expected {:?} {}",
+                self.index, self.field, self.expected
+            ),
+        }
+    }
+}
+
+impl PublicInputSchema {
+    /// Whether the whole of `field` is bound, either to a single input or split across two
+    fn binds(&self, field: PacketField) -> bool {
+        let has = |encoding| {
+            self.0
+                .iter()
+                .any(|binding| binding.field == field && binding.encoding == encoding)
+        };
+        has(Encoding::Reduced) || (has(Encoding::High) && has(Encoding::Low))
+    }
+
+    /// Check every binding against `public_inputs`, returning the inputs that do not commit to
+    /// the packet of `context`
+    pub fn mismatches(
+        &self,
+        public_inputs: &[Fr],
+        context: &VerificationContext,
+    ) -> Result<Vec<InputMismatch>, Error> {
+        let header = PacketHeader::decode(&context.header)?;
+
+        let mut mismatches = Vec::new();
+        for binding in &self.0 {
+            let expected = match binding.field {
+                PacketField::SrcEid => integer(header.src_eid.into(), binding)?,
+                PacketField::DstEid => integer(header.dst_eid.into(), binding)?,
+                PacketField::Nonce => integer(header.nonce, binding)?,
+                PacketField::PayloadHash => hash(context.payload_hash, binding.encoding),
+                PacketField::SourceBlockHash => hash(context.source_block_hash, binding.encoding),
+                PacketField::MessageId => hash(context.message_id, binding.encoding),
+            };
+
+            let input = public_inputs.get(binding.index).copied();
+            if input != Some(expected) {
+                mismatches.push(InputMismatch {
+                    index: binding.index,
+                    field: binding.field,
+                    input,
+                    expected,
+                });
+            }
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Whether `public_inputs` commit to the packet of `context`, logging every input that
+    /// does not
+    pub fn commits_to_packet(
+        &self,
+        public_inputs: &[Fr],
+        context: &VerificationContext,
+    ) -> Result<bool, Error> {
+        let mismatches = self.mismatches(public_inputs, context)?;
+        if !mismatches.is_empty() {
+            tracing::warn!(
+                "Proof public inputs do not commit to message {}: {}",
+                context.message_id,
+                mismatches
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            );
+        }
+
+        Ok(mismatches.is_empty())
+    }
+}
+
+fn integer(value: u64, binding: &PublicInputBinding) -> Result<Fr, Error> {
+    if binding.encoding != Encoding::Reduced {
+        return Err(Error::Client(format!(
+            "{:?} of public input {} cannot be split into {:?} bits",
+            binding.field, binding.index, binding.encoding
+        )));
+    }
+
+    Ok(Fr::from(value))
+}
+
+fn hash(value: B256, encoding: Encoding) -> Fr {
+    match encoding {
+        Encoding::Reduced => bn254::hash_input(value),
+        Encoding::High => Fr::from_be_bytes_mod_order(&value[..16]),
+        Encoding::Low => Fr::from_be_bytes_mod_order(&value[16..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{self, test_packets};
+    use alloy_primitives::{Address, Bytes};
+
+    fn context() -> VerificationContext {
+        let packet = test_packets::packet(7, 30101);
+
+        VerificationContext {
+            chain_id: 1,
+            verifier_address: Address::ZERO,
+            message_id: codec::packet_message_id(&packet),
+            header: PacketHeader::from(&packet).encode(),
+            payload_hash: codec::payload_hash(&packet),
+            source_block_hash: B256::repeat_byte(0xcd),
+            extra_data: Bytes::new(),
+        }
+    }
+
+    fn binding(index: usize, field: PacketField, encoding: Encoding) -> PublicInputBinding {
+        PublicInputBinding {
+            index,
+            field,
+            encoding,
+        }
+    }
+
+    fn full_schema() -> PublicInputSchema {
+        PublicInputSchema(vec![
+            binding(0, PacketField::SrcEid, Encoding::Reduced),
+            binding(1, PacketField::DstEid, Encoding::Reduced),
+            binding(2, PacketField::Nonce, Encoding::Reduced),
+            binding(3, PacketField::PayloadHash, Encoding::High),
+            binding(4, PacketField::PayloadHash, Encoding::Low),
+            binding(5, PacketField::SourceBlockHash, Encoding::Reduced),
+        ])
+    }
+
+    fn packet_inputs() -> Vec<Fr> {
+        let payload_hash = context().payload_hash;
+        let half = |bytes: &[u8]| Fr::from(u128::from_be_bytes(bytes.try_into().unwrap()));
+        vec![
+            Fr::from(30101u64),
+            Fr::from(30110u64),
+            Fr::from(7u64),
+            half(&payload_hash[..16]),
+            half(&payload_hash[16..]),
+            Fr::from_be_bytes_mod_order(&[0xcd; 32]),
+            // An input the schema leaves to the circuit
+            Fr::from(9u64),
+        ]
+    }
+
+    #[test]
+    fn test_inputs_committing_to_packet() {
+        let context = context();
+        assert!(full_schema()
+            .commits_to_packet(&packet_inputs(), &context)
+            .unwrap());
+
+        let message_id = bn254::hash_input(context.message_id);
+        assert!(PublicInputSchema::default()
+            .commits_to_packet(&[message_id], &context)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_reports_each_mismatching_input() {
+        let mut inputs = packet_inputs();
+        inputs[2] = Fr::from(8u64);
+        inputs[5] = Fr::from(0u64);
+
+        let mismatches = full_schema().mismatches(&inputs, &context()).unwrap();
+        assert_eq!(
+            mismatches
+                .iter()
+                .map(|mismatch| (mismatch.index, mismatch.field))
+                .collect::<Vec<_>>(),
+            [(2, PacketField::Nonce), (5, PacketField::SourceBlockHash)]
+        );
+        assert_eq!(mismatches[0].expected, Fr::from(7u64));
+
+        // A proof with too few inputs cannot commit to the packet
+        let mismatches = full_schema()
+            .mismatches(&packet_inputs()[..4], &context())
+            .unwrap();
+        assert_eq!(mismatches.len(), 2);
+        assert!(mismatches.iter().all(|mismatch| mismatch.input.is_none()));
+    }
+
+    #[test]
+    fn test_rejects_split_integer_fields() {
+        let schema = PublicInputSchema(vec![binding(0, PacketField::Nonce, Encoding::High)]);
+        assert!(schema.mismatches(&packet_inputs(), &context()).is_err());
+    }
+
+    #[test]
+    fn test_schema_from_config() {
+        let schema: PublicInputSchema = serde_json::from_str(
+            r#"[
+                { "index": 0, "field": "src_eid" },
+                { "index": 2, "field": "nonce" },
+                { "index": 3, "field": "payload_hash", "encoding": "high" },
+                { "index": 4, "field": "payload_hash", "encoding": "low" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            schema.0,
+            [
+                binding(0, PacketField::SrcEid, Encoding::Reduced),
+                binding(2, PacketField::Nonce, Encoding::Reduced),
+                binding(3, PacketField::PayloadHash, Encoding::High),
+                binding(4, PacketField::PayloadHash, Encoding::Low),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_schema_not_binding_packet() {
+        let parse = |json| serde_json::from_str::<PublicInputSchema>(json);
+
+        assert!(parse("[]").is_err());
+        assert!(parse(r#"[{ "index": 0, "field": "source_block_hash" }]"#).is_err());
+        // Half of the payload hash does not identify the packet
+        assert!(parse(
+            r#"[
+                { "index": 0, "field": "nonce" },
+                { "index": 1, "field": "payload_hash", "encoding": "high" }
+            ]"#
+        )
+        .is_err());
+        assert!(parse(r#"[{ "index": 0, "field": "message_id", "encoding": "low" }]"#).is_err());
+
+        assert!(parse(r#"[{ "index": 0, "field": "message_id" }]"#).is_ok());
+    }
+}
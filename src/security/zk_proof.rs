@@ -1,4 +1,11 @@
+use super::bn254;
+use super::gnark_plonk::GnarkPlonkVerifier;
+use super::groth16::{self, Groth16Verifier};
+use super::plonk::{self, PlonkVerifier, VersionedProof};
+use super::prover::Receipt;
+use super::public_inputs::PublicInputSchema;
 use super::{SecurityVerifier, VerificationContext};
+use crate::options::PacketOptions;
 use alloy_primitives::Bytes;
 use async_trait::async_trait;
 use gadget_sdk::Error;
@@ -10,21 +17,48 @@ pub struct ZkProofVerifier {
     verification_key: Bytes,
     /// The proof system identifier (e.g., "groth16", "plonk", etc.)
     proof_system: String,
+    /// Public inputs Groth16 and PLONK proofs must bind to the packet
+    public_inputs: PublicInputSchema,
 }
 
 impl ZkProofVerifier {
-    pub fn new(verification_key: Bytes, proof_system: String) -> Self {
+    pub fn new(
+        verification_key: Bytes,
+        proof_system: String,
+        public_inputs: PublicInputSchema,
+    ) -> Self {
         Self {
             verification_key,
             proof_system,
+            public_inputs,
         }
     }
 
+    /// Whether a DVN option addressed to us carries the proof, rather than the prover
+    pub fn has_option_proof(context: &VerificationContext) -> Result<bool, Error> {
+        Ok(!PacketOptions::decode(&context.extra_data)?.dvn.is_empty())
+    }
+
+    /// Verify `receipt`, the proof of the packet fetched from the prover, with the same packet
+    /// binding as a proof carried in the DVN options
+    pub fn verify_receipt(
+        &self,
+        receipt: &Receipt,
+        context: &VerificationContext,
+    ) -> Result<bool, Error> {
+        self.verify_proof(&receipt.proof, &receipt.public_inputs, context)
+    }
+
     /// Verify a proof using the specified proof system
-    fn verify_proof(&self, proof: &[u8], public_inputs: &[u8]) -> Result<bool, Error> {
+    fn verify_proof(
+        &self,
+        proof: &[u8],
+        public_inputs: &[u8],
+        context: &VerificationContext,
+    ) -> Result<bool, Error> {
         match self.proof_system.as_str() {
-            "groth16" => self.verify_groth16(proof, public_inputs),
-            "plonk" => self.verify_plonk(proof, public_inputs),
+            "groth16" => self.verify_groth16(proof, public_inputs, context),
+            "plonk" => self.verify_plonk(proof, public_inputs, context),
             _ => Err(Error::Client(format!(
                 "Unsupported proof system: {}",
                 self.proof_system
@@ -32,34 +66,233 @@ impl ZkProofVerifier {
         }
     }
 
-    fn verify_groth16(&self, _proof: &[u8], _public_inputs: &[u8]) -> Result<bool, Error> {
-        // TODO: Implement actual Groth16 verification
-        // This would typically use ark-groth16 or similar
-        unimplemented!("Groth16 verification not implemented")
+    /// Verify a Groth16 proof over BN254 against a snarkjs or gnark verifying key. The public
+    /// inputs must commit to the packet as described by the schema, so a proof for one packet
+    /// cannot be replayed for another.
+    fn verify_groth16(
+        &self,
+        proof: &[u8],
+        public_inputs: &[u8],
+        context: &VerificationContext,
+    ) -> Result<bool, Error> {
+        let verifier = Groth16Verifier::from_json(&self.verification_key)?;
+        let proof = groth16::decode_proof(proof)?;
+        let public_inputs = bn254::decode_public_inputs(public_inputs)?;
+
+        if !self
+            .public_inputs
+            .commits_to_packet(&public_inputs, context)?
+        {
+            return Ok(false);
+        }
+
+        verifier.verify(&proof, &public_inputs)
     }
 
-    fn verify_plonk(&self, _proof: &[u8], _public_inputs: &[u8]) -> Result<bool, Error> {
-        // TODO: Implement actual PLONK verification
-        unimplemented!("PLONK verification not implemented")
+    /// Verify a versioned PLONK proof over BN254, with the same packet binding as Groth16. The
+    /// proof's serialization version names its toolchain, whose verifying key format is expected.
+    fn verify_plonk(
+        &self,
+        proof: &[u8],
+        public_inputs: &[u8],
+        context: &VerificationContext,
+    ) -> Result<bool, Error> {
+        let proof = plonk::decode_proof(proof)?;
+        let public_inputs = bn254::decode_public_inputs(public_inputs)?;
+
+        if !self
+            .public_inputs
+            .commits_to_packet(&public_inputs, context)?
+        {
+            return Ok(false);
+        }
+
+        match proof {
+            VersionedProof::Snarkjs(proof) => {
+                PlonkVerifier::from_snarkjs_json(&self.verification_key)?
+                    .verify(&proof, &public_inputs)
+            }
+            VersionedProof::Gnark(proof) => GnarkPlonkVerifier::from_json(&self.verification_key)?
+                .verify(&proof, &public_inputs),
+        }
     }
 }
 
 #[async_trait]
 impl SecurityVerifier for ZkProofVerifier {
-    async fn verify(&self, data: &[u8], context: &VerificationContext) -> Result<bool, Error> {
-        // Extract proof and public inputs from context.extra_data
+    async fn verify(&self, _data: &[u8], context: &VerificationContext) -> Result<bool, Error> {
+        // The proof is carried in the params of the first DVN option addressed to us
         // Format: [proof_len (4 bytes) || proof || public_inputs]
-        if context.extra_data.len() < 4 {
+        let proof_data = PacketOptions::decode(&context.extra_data)?
+            .dvn
+            .into_iter()
+            .next()
+            .map(|option| option.params)
+            .ok_or_else(|| Error::Client("No proof in the DVN options".into()))?;
+
+        if proof_data.len() < 4 {
             return Err(Error::Client("Invalid proof data format".into()));
         }
 
-        let proof_len = u32::from_be_bytes(context.extra_data[..4].try_into()?)
+        let proof_len: usize = u32::from_be_bytes(proof_data[..4].try_into()?)
             .try_into()
             .map_err(|e| Error::Client(format!("Invalid proof length: {}", e)))?;
+        if proof_data.len() < 4 + proof_len {
+            return Err(Error::Client("Proof length exceeds the proof data".into()));
+        }
+
+        let proof = &proof_data[4..4 + proof_len];
+        let public_inputs = &proof_data[4 + proof_len..];
+
+        self.verify_proof(proof, public_inputs, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::options::DvnOption;
+    use crate::security::{gnark_plonk, groth16, plonk};
+    use alloy_primitives::{Address, B256};
+
+    /// Header and payload hash of `packet_v1.json`'s `case0`, which the fixture proofs commit to
+    const HEADER: &str = "0x0100000000000000010000759500000000000000000000000011111111111111111111111111111111111111110000759e0000000000000000000000002222222222222222222222222222222222222222";
+    const PAYLOAD_HASH: &str = "0x1b8e857e638d149cefcaae538d909c5c5640059b7185bdcc8fe0ac7546cb23c5";
+
+    fn context(payload_hash: B256, proof: Vec<u8>, public_inputs: Vec<u8>) -> VerificationContext {
+        let mut params = (proof.len() as u32).to_be_bytes().to_vec();
+        params.extend_from_slice(&proof);
+        params.extend_from_slice(&public_inputs);
+
+        let options = PacketOptions {
+            executor: Vec::new(),
+            dvn: vec![DvnOption {
+                index: 0,
+                option_type: 1,
+                params: params.into(),
+            }],
+        };
+
+        let header: Bytes = HEADER.parse().unwrap();
+        VerificationContext {
+            chain_id: 1,
+            verifier_address: Address::ZERO,
+            message_id: codec::message_id(&header, payload_hash),
+            header,
+            payload_hash,
+            source_block_hash: B256::ZERO,
+            extra_data: options.encode_dvn_options(0),
+        }
+    }
+
+    async fn assert_bound_to_packet(
+        verifier: ZkProofVerifier,
+        proof: Vec<u8>,
+        public_inputs: Vec<u8>,
+    ) {
+        let bound = context(
+            PAYLOAD_HASH.parse().unwrap(),
+            proof.clone(),
+            public_inputs.clone(),
+        );
+        assert!(verifier.verify(&[], &bound).await.unwrap());
+
+        let other = context(B256::repeat_byte(0x55), proof, public_inputs);
+        assert!(!verifier.verify(&[], &other).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_groth16_proof_is_bound_to_message_id() {
+        let verifier = ZkProofVerifier::new(
+            Bytes::from_static(groth16::tests::VERIFICATION_KEY.as_bytes()),
+            "groth16".into(),
+            PublicInputSchema::default(),
+        );
+        assert_bound_to_packet(
+            verifier,
+            groth16::tests::proof_bytes(),
+            groth16::tests::public_input_bytes(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_gnark_groth16_proof_is_bound_to_message_id() {
+        let verifier = ZkProofVerifier::new(
+            Bytes::from_static(groth16::tests::GNARK_VERIFICATION_KEY.as_bytes()),
+            "groth16".into(),
+            PublicInputSchema::default(),
+        );
+        assert_bound_to_packet(
+            verifier,
+            groth16::tests::gnark_proof_bytes(),
+            groth16::tests::public_input_bytes(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_plonk_proof_is_bound_to_message_id() {
+        let verifier = ZkProofVerifier::new(
+            Bytes::from_static(plonk::tests::VERIFICATION_KEY.as_bytes()),
+            "plonk".into(),
+            PublicInputSchema::default(),
+        );
+        assert_bound_to_packet(
+            verifier,
+            plonk::tests::proof_bytes(),
+            plonk::tests::public_input_bytes(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_gnark_plonk_proof_is_bound_to_message_id() {
+        let mut proof = vec![plonk::SERIALIZATION_V2];
+        proof.extend_from_slice(&gnark_plonk::tests::proof_bytes());
+        let verifier = ZkProofVerifier::new(
+            Bytes::from_static(gnark_plonk::tests::VERIFICATION_KEY.as_bytes()),
+            "plonk".into(),
+            PublicInputSchema::default(),
+        );
+        assert_bound_to_packet(verifier, proof.clone(), plonk::tests::public_input_bytes()).await;
+
+        // A gnark proof is not checked against a snarkjs key
+        let snarkjs = ZkProofVerifier::new(
+            Bytes::from_static(plonk::tests::VERIFICATION_KEY.as_bytes()),
+            "plonk".into(),
+            PublicInputSchema::default(),
+        );
+        let context = context(
+            PAYLOAD_HASH.parse().unwrap(),
+            proof,
+            plonk::tests::public_input_bytes(),
+        );
+        assert!(snarkjs.verify(&[], &context).await.is_err());
+    }
+
+    #[test]
+    fn test_groth16_proof_from_prover() {
+        let receipt = Receipt {
+            proof: groth16::tests::proof_bytes().into(),
+            public_inputs: groth16::tests::public_input_bytes().into(),
+        };
+        let verifier = ZkProofVerifier::new(
+            Bytes::from_static(groth16::tests::VERIFICATION_KEY.as_bytes()),
+            "groth16".into(),
+            PublicInputSchema::default(),
+        );
 
-        let proof = &context.extra_data[4..4 + proof_len];
-        let public_inputs = &context.extra_data[4 + proof_len..];
+        let bound = VerificationContext {
+            extra_data: Bytes::new(),
+            ..context(PAYLOAD_HASH.parse().unwrap(), Vec::new(), Vec::new())
+        };
+        assert!(!ZkProofVerifier::has_option_proof(&bound).unwrap());
+        assert!(verifier.verify_receipt(&receipt, &bound).unwrap());
 
-        self.verify_proof(proof, public_inputs)
+        let other = context(B256::repeat_byte(0x55), Vec::new(), Vec::new());
+        assert!(ZkProofVerifier::has_option_proof(&other).unwrap());
+        assert!(!verifier.verify_receipt(&receipt, &other).unwrap());
     }
 }
@@ -0,0 +1,195 @@
+//! Packet store backed by the gadget SDK's `LocalDatabase`
+
+use super::{PacketStore, StoredPacket};
+use crate::queue::{JobRecord, JobState};
+use alloy_primitives::B256;
+use async_trait::async_trait;
+use gadget_sdk::store::LocalDatabase;
+use gadget_sdk::Error;
+use std::path::Path;
+use tokio::sync::Mutex;
+
+/// Key under which the index of stored message IDs is kept
+const IDS_KEY: &str = "ids";
+/// Key under which the index of in-progress jobs is kept
+const PENDING_JOBS_KEY: &str = "pending";
+/// Key under which the index of every job is kept
+const ALL_JOBS_KEY: &str = "all";
+
+/// Packets and jobs kept in JSON files, keyed by the hex-encoded message ID. `LocalDatabase` cannot
+/// delete keys, so removed entries are kept as `null`. Lookups other than by message ID scan every
+/// packet, and each file is rewritten on every update, so this backend suits small deployments
+/// only.
+#[derive(Debug)]
+pub struct LocalPacketStore {
+    packets: LocalDatabase<Option<StoredPacket>>,
+    statuses: LocalDatabase<Option<JobState>>,
+    ids: LocalDatabase<Vec<B256>>,
+    jobs: LocalDatabase<Option<JobRecord>>,
+    job_ids: LocalDatabase<Vec<B256>>,
+    /// Serializes read-modify-write updates of the packet ID index
+    lock: Mutex<()>,
+    /// Serializes read-modify-write updates of the job indexes
+    jobs_lock: Mutex<()>,
+}
+
+impl LocalPacketStore {
+    pub fn open(dir: &Path) -> Self {
+        Self {
+            packets: LocalDatabase::open(dir.join("packets.json")),
+            statuses: LocalDatabase::open(dir.join("packet_status.json")),
+            ids: LocalDatabase::open(dir.join("packet_ids.json")),
+            jobs: LocalDatabase::open(dir.join("jobs.json")),
+            job_ids: LocalDatabase::open(dir.join("pending_jobs.json")),
+            lock: Mutex::new(()),
+            jobs_lock: Mutex::new(()),
+        }
+    }
+
+    fn job_ids(&self, key: &str) -> Vec<B256> {
+        self.job_ids.get(key).unwrap_or_default()
+    }
+
+    async fn jobs_for(&self, key: &str) -> Result<Vec<JobRecord>, Error> {
+        let mut records = Vec::new();
+        for message_id in self.job_ids(key) {
+            if let Some(record) = self.get_job(message_id).await? {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+
+    async fn find(&self, matches: impl Fn(&StoredPacket) -> bool) -> Result<Vec<B256>, Error> {
+        let mut found = Vec::new();
+        for message_id in self.message_ids().await? {
+            if let Some(packet) = self.get(message_id).await? {
+                if matches(&packet) {
+                    found.push(message_id);
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+#[async_trait]
+impl PacketStore for LocalPacketStore {
+    async fn get(&self, message_id: B256) -> Result<Option<StoredPacket>, Error> {
+        Ok(self.packets.get(&message_id.to_string()).flatten())
+    }
+
+    async fn insert(&self, message_id: B256, packet: &StoredPacket) -> Result<bool, Error> {
+        let _guard = self.lock.lock().await;
+        let key = message_id.to_string();
+        let inserted = self.get(message_id).await?.is_none();
+        self.packets.set(&key, Some(packet.clone()));
+
+        if inserted {
+            self.statuses.set(&key, Some(JobState::Stored));
+            let mut ids = self.message_ids().await?;
+            ids.push(message_id);
+            self.ids.set(IDS_KEY, ids);
+        }
+
+        Ok(inserted)
+    }
+
+    async fn set_status(&self, message_id: B256, status: JobState) -> Result<(), Error> {
+        if self.get(message_id).await?.is_none() {
+            return Ok(());
+        }
+
+        self.statuses.set(&message_id.to_string(), Some(status));
+        Ok(())
+    }
+
+    async fn remove(&self, message_id: B256) -> Result<(), Error> {
+        let _guard = self.lock.lock().await;
+        let key = message_id.to_string();
+        self.packets.set(&key, None);
+        self.statuses.set(&key, None);
+
+        let mut ids = self.message_ids().await?;
+        ids.retain(|id| *id != message_id);
+        self.ids.set(IDS_KEY, ids);
+        Ok(())
+    }
+
+    async fn message_ids(&self) -> Result<Vec<B256>, Error> {
+        Ok(self.ids.get(IDS_KEY).unwrap_or_default())
+    }
+
+    async fn find_by_nonce(&self, nonce: u64) -> Result<Vec<B256>, Error> {
+        self.find(|stored| stored.packet.nonce == nonce).await
+    }
+
+    async fn find_by_src_eid(&self, src_eid: u32) -> Result<Vec<B256>, Error> {
+        self.find(|stored| stored.packet.srcEid == src_eid).await
+    }
+
+    async fn find_by_status(&self, status: JobState) -> Result<Vec<B256>, Error> {
+        Ok(self
+            .message_ids()
+            .await?
+            .into_iter()
+            .filter(|message_id| {
+                self.statuses.get(&message_id.to_string()).flatten() == Some(status)
+            })
+            .collect())
+    }
+
+    async fn get_job(&self, message_id: B256) -> Result<Option<JobRecord>, Error> {
+        Ok(self.jobs.get(&message_id.to_string()).flatten())
+    }
+
+    async fn put_job(&self, record: &JobRecord) -> Result<(), Error> {
+        let message_id = record.message_id;
+        self.jobs.set(&message_id.to_string(), Some(record.clone()));
+        self.set_status(message_id, record.state).await?;
+
+        let _guard = self.jobs_lock.lock().await;
+        let mut all = self.job_ids(ALL_JOBS_KEY);
+        if !all.contains(&message_id) {
+            all.push(message_id);
+            self.job_ids.set(ALL_JOBS_KEY, all);
+        }
+
+        let mut pending = self.job_ids(PENDING_JOBS_KEY);
+        let indexed = pending.contains(&message_id);
+        if record.state.is_in_progress() && !indexed {
+            pending.push(message_id);
+            self.job_ids.set(PENDING_JOBS_KEY, pending);
+        } else if !record.state.is_in_progress() && indexed {
+            pending.retain(|id| *id != message_id);
+            self.job_ids.set(PENDING_JOBS_KEY, pending);
+        }
+
+        Ok(())
+    }
+
+    async fn remove_job(&self, message_id: B256) -> Result<(), Error> {
+        self.jobs.set(&message_id.to_string(), None);
+
+        let _guard = self.jobs_lock.lock().await;
+        for key in [ALL_JOBS_KEY, PENDING_JOBS_KEY] {
+            let mut ids = self.job_ids(key);
+            if ids.contains(&message_id) {
+                ids.retain(|id| *id != message_id);
+                self.job_ids.set(key, ids);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn jobs(&self) -> Result<Vec<JobRecord>, Error> {
+        self.jobs_for(ALL_JOBS_KEY).await
+    }
+
+    async fn pending_jobs(&self) -> Result<Vec<JobRecord>, Error> {
+        self.jobs_for(PENDING_JOBS_KEY).await
+    }
+}
@@ -0,0 +1,240 @@
+//! Storage of the packets seen on the source chains and of their jobs, behind a pluggable
+//! [`PacketStore`] backend
+
+use crate::queue::{JobRecord, JobState};
+use crate::ISendLib::Packet;
+use alloy_primitives::{Bytes, B256};
+use async_trait::async_trait;
+use gadget_sdk::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+mod local;
+mod sled;
+mod sqlite;
+
+pub use self::local::LocalPacketStore;
+pub use self::sled::SledPacketStore;
+pub use self::sqlite::SqlitePacketStore;
+
+/// Stored packet information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredPacket {
+    pub packet: Packet,
+    pub options: Bytes,
+    /// Source chain block containing the `PacketSent` log
+    pub block_number: u64,
+    /// Hash of the block containing the `PacketSent` log, used to detect reorgs
+    pub block_hash: B256,
+    /// Source chain transaction that emitted the `PacketSent` log
+    pub transaction_hash: B256,
+    /// When the packet was stored, in seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl StoredPacket {
+    pub(crate) fn new(
+        packet: Packet,
+        options: Bytes,
+        log: &gadget_sdk::alloy_rpc_types::Log,
+    ) -> Result<Self, Error> {
+        let (Some(block_number), Some(block_hash), Some(transaction_hash)) =
+            (log.block_number, log.block_hash, log.transaction_hash)
+        else {
+            return Err(Error::Client("PacketSent log is still pending".into()));
+        };
+
+        Ok(Self {
+            packet,
+            options,
+            block_number,
+            block_hash,
+            transaction_hash,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+        })
+    }
+}
+
+/// Backend used to store packets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// The gadget SDK's `LocalDatabase`, JSON files scanned for every lookup
+    #[default]
+    Local,
+    /// An embedded sled database
+    Sled,
+    /// An SQLite database
+    Sqlite,
+}
+
+/// Packets keyed by message ID, along with the state of their jobs, indexed by nonce, source
+/// EID and state, and the job records themselves
+#[async_trait]
+pub trait PacketStore: fmt::Debug + Send + Sync {
+    async fn get(&self, message_id: B256) -> Result<Option<StoredPacket>, Error>;
+
+    /// Store `packet` under `message_id`, keeping the state of a packet that was already stored.
+    /// Returns whether the packet was newly inserted.
+    async fn insert(&self, message_id: B256, packet: &StoredPacket) -> Result<bool, Error>;
+
+    /// Record the state of the job for `message_id`, if its packet is stored
+    async fn set_status(&self, message_id: B256, status: JobState) -> Result<(), Error>;
+
+    async fn remove(&self, message_id: B256) -> Result<(), Error>;
+
+    /// Message IDs of every stored packet
+    async fn message_ids(&self) -> Result<Vec<B256>, Error>;
+
+    /// Message IDs of the stored packets with the given nonce
+    async fn find_by_nonce(&self, nonce: u64) -> Result<Vec<B256>, Error>;
+
+    /// Message IDs of the stored packets sent from `src_eid`
+    async fn find_by_src_eid(&self, src_eid: u32) -> Result<Vec<B256>, Error>;
+
+    /// Message IDs of the stored packets whose jobs are in `status`
+    async fn find_by_status(&self, status: JobState) -> Result<Vec<B256>, Error>;
+
+    /// Job record of `message_id`, which may exist before its packet is stored
+    async fn get_job(&self, message_id: B256) -> Result<Option<JobRecord>, Error>;
+
+    /// Persist `record`, mirroring its state into the status of its packet if that is stored
+    async fn put_job(&self, record: &JobRecord) -> Result<(), Error>;
+
+    async fn remove_job(&self, message_id: B256) -> Result<(), Error>;
+
+    /// Every job record, including those of packets we were never assigned
+    async fn jobs(&self) -> Result<Vec<JobRecord>, Error>;
+
+    /// Job records that were assigned to us and have not reached a terminal state
+    async fn pending_jobs(&self) -> Result<Vec<JobRecord>, Error>;
+}
+
+/// Open the packet store kept in `dir` with the given backend
+pub fn open(backend: StorageBackend, dir: &Path) -> Result<Arc<dyn PacketStore>, Error> {
+    Ok(match backend {
+        StorageBackend::Local => Arc::new(LocalPacketStore::open(dir)),
+        StorageBackend::Sled => Arc::new(SledPacketStore::open(dir)?),
+        StorageBackend::Sqlite => Arc::new(SqlitePacketStore::open(dir)?),
+    })
+}
+
+fn store_error(e: impl fmt::Display) -> Error {
+    Error::Client(format!("Packet store error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_packets::stored_packet;
+
+    async fn exercise(store: &dyn PacketStore) {
+        let (a, packet_a) = stored_packet(1, 30101);
+        let (b, packet_b) = stored_packet(2, 30101);
+        let (c, packet_c) = stored_packet(1, 30102);
+
+        assert!(store.insert(a, &packet_a).await.unwrap());
+        assert!(store.insert(b, &packet_b).await.unwrap());
+        assert!(store.insert(c, &packet_c).await.unwrap());
+        assert_eq!(store.get(a).await.unwrap().unwrap().packet, packet_a.packet);
+
+        store.set_status(b, JobState::Assigned).await.unwrap();
+        let mut moved = packet_b.clone();
+        moved.block_number = 10;
+        assert!(!store.insert(b, &moved).await.unwrap());
+        assert_eq!(store.get(b).await.unwrap().unwrap().block_number, 10);
+
+        let mut by_nonce = store.find_by_nonce(1).await.unwrap();
+        by_nonce.sort();
+        let mut expected = vec![a, c];
+        expected.sort();
+        assert_eq!(by_nonce, expected);
+        assert_eq!(store.find_by_src_eid(30102).await.unwrap(), [c]);
+        assert_eq!(store.find_by_status(JobState::Assigned).await.unwrap(), [b]);
+        assert_eq!(
+            store.find_by_status(JobState::Stored).await.unwrap().len(),
+            2
+        );
+
+        store.remove(a).await.unwrap();
+        assert!(store.get(a).await.unwrap().is_none());
+        assert_eq!(store.find_by_nonce(1).await.unwrap(), [c]);
+        assert_eq!(store.message_ids().await.unwrap().len(), 2);
+
+        // Status updates for packets that are not stored are ignored
+        store.set_status(a, JobState::Finalized).await.unwrap();
+        assert!(store
+            .find_by_status(JobState::Finalized)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Job records mirror their state into the packet status, and may exist before their
+        // packet is stored
+        let mut job = JobRecord::new(c);
+        job.state = JobState::Confirming;
+        store.put_job(&job).await.unwrap();
+        let mut parked = JobRecord::new(a);
+        parked.state = JobState::Assigned;
+        store.put_job(&parked).await.unwrap();
+        assert_eq!(
+            store.get_job(c).await.unwrap().unwrap().state,
+            JobState::Confirming
+        );
+        assert_eq!(
+            store.find_by_status(JobState::Confirming).await.unwrap(),
+            [c]
+        );
+        assert_eq!(store.jobs().await.unwrap().len(), 2);
+        assert_eq!(store.pending_jobs().await.unwrap().len(), 2);
+
+        job.state = JobState::Finalized;
+        store.put_job(&job).await.unwrap();
+        let pending = store.pending_jobs().await.unwrap();
+        assert_eq!(
+            pending.iter().map(|job| job.message_id).collect::<Vec<_>>(),
+            [a]
+        );
+        assert_eq!(
+            store.find_by_status(JobState::Finalized).await.unwrap(),
+            [c]
+        );
+
+        store.remove_job(a).await.unwrap();
+        assert!(store.get_job(a).await.unwrap().is_none());
+        assert!(store.pending_jobs().await.unwrap().is_empty());
+        assert_eq!(store.jobs().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_local_store() {
+        let dir = tempfile::tempdir().unwrap();
+        exercise(&LocalPacketStore::open(dir.path())).await;
+    }
+
+    #[tokio::test]
+    async fn test_sled_store() {
+        let dir = tempfile::tempdir().unwrap();
+        exercise(&SledPacketStore::open(dir.path()).unwrap()).await;
+    }
+
+    #[test]
+    fn test_sled_store_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let _running = SledPacketStore::open(dir.path()).unwrap();
+        let Err(Error::Client(e)) = SledPacketStore::open(dir.path()) else {
+            panic!("A locked sled database was opened");
+        };
+        assert!(e.contains("in use by another process"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let dir = tempfile::tempdir().unwrap();
+        exercise(&SqlitePacketStore::open(dir.path()).unwrap()).await;
+    }
+}
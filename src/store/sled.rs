@@ -0,0 +1,277 @@
+//! Packet store backed by an embedded sled database
+
+use super::{store_error, PacketStore, StoredPacket};
+use crate::queue::{JobRecord, JobState};
+use alloy_primitives::B256;
+use async_trait::async_trait;
+use gadget_sdk::Error;
+use sled::transaction::{
+    abort, ConflictableTransactionResult, TransactionError, TransactionalTree,
+};
+use sled::{Transactional, Tree};
+use std::path::Path;
+
+/// Marker value of index entries, whose keys carry all the information
+const INDEXED: &[u8] = &[];
+
+/// Result of the body of a transaction, which aborts with a packet store error
+type TransactionResult<T> = ConflictableTransactionResult<T, Error>;
+
+/// Packets and jobs kept in sled trees, with one index tree per lookup. Index keys are the
+/// indexed value followed by the message ID, so lookups are prefix scans. Updates spanning
+/// several trees are made in a single transaction, so a crash cannot leave a packet without its
+/// status or index entries.
+#[derive(Debug)]
+pub struct SledPacketStore {
+    packets: Tree,
+    statuses: Tree,
+    by_nonce: Tree,
+    by_src_eid: Tree,
+    by_status: Tree,
+    jobs: Tree,
+    /// Message IDs of the jobs in progress
+    pending_jobs: Tree,
+}
+
+impl SledPacketStore {
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join("packets.sled");
+        let db = sled::open(&path).map_err(|e| match e {
+            // sled only reports the lock held by another process through the message
+            sled::Error::Io(e) if e.to_string().starts_with("could not acquire lock") => {
+                Error::Client(format!(
+                    "The sled database {} is in use by another process, such as the running DVN, \
+                     which must be stopped first",
+                    path.display()
+                ))
+            }
+            e => store_error(e),
+        })?;
+        let tree = |name: &str| db.open_tree(name).map_err(store_error);
+
+        Ok(Self {
+            packets: tree("packets")?,
+            statuses: tree("statuses")?,
+            by_nonce: tree("by_nonce")?,
+            by_src_eid: tree("by_src_eid")?,
+            by_status: tree("by_status")?,
+            jobs: tree("jobs")?,
+            pending_jobs: tree("pending_jobs")?,
+        })
+    }
+
+    fn job(&self, message_id: B256) -> Result<Option<JobRecord>, Error> {
+        self.jobs
+            .get(message_id)
+            .map_err(store_error)?
+            .map(|record| serde_json::from_slice(&record).map_err(store_error))
+            .transpose()
+    }
+}
+
+/// Move a stored packet to `status` in the status index, within a transaction over the
+/// `statuses` and `by_status` trees
+fn update_status(
+    statuses: &TransactionalTree,
+    by_status: &TransactionalTree,
+    message_id: B256,
+    status: JobState,
+) -> TransactionResult<()> {
+    let Some(previous) = statuses
+        .get(message_id)?
+        .and_then(|status| status.first().copied())
+    else {
+        return Ok(());
+    };
+
+    let status = status as u8;
+    by_status.remove(index_key(&[previous], message_id))?;
+    by_status.insert(index_key(&[status], message_id), INDEXED)?;
+    statuses.insert(message_id.as_slice(), vec![status])?;
+    Ok(())
+}
+
+/// Error of a finished transaction, passing through the error it aborted with
+fn transaction_error(e: TransactionError<Error>) -> Error {
+    match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => store_error(e),
+    }
+}
+
+#[async_trait]
+impl PacketStore for SledPacketStore {
+    async fn get(&self, message_id: B256) -> Result<Option<StoredPacket>, Error> {
+        self.packets
+            .get(message_id)
+            .map_err(store_error)?
+            .map(|packet| serde_json::from_slice(&packet).map_err(store_error))
+            .transpose()
+    }
+
+    async fn insert(&self, message_id: B256, packet: &StoredPacket) -> Result<bool, Error> {
+        let encoded = serde_json::to_vec(packet).map_err(store_error)?;
+        let nonce_key = index_key(&packet.packet.nonce.to_be_bytes(), message_id);
+        let src_eid_key = index_key(&packet.packet.srcEid.to_be_bytes(), message_id);
+
+        (
+            &self.packets,
+            &self.statuses,
+            &self.by_nonce,
+            &self.by_src_eid,
+            &self.by_status,
+        )
+            .transaction(
+                |(packets, statuses, by_nonce, by_src_eid, by_status)| -> TransactionResult<bool> {
+                    let inserted = packets
+                        .insert(message_id.as_slice(), encoded.as_slice())?
+                        .is_none();
+
+                    if inserted {
+                        let status = JobState::Stored as u8;
+                        statuses.insert(message_id.as_slice(), vec![status])?;
+                        by_nonce.insert(nonce_key.as_slice(), INDEXED)?;
+                        by_src_eid.insert(src_eid_key.as_slice(), INDEXED)?;
+                        by_status.insert(index_key(&[status], message_id), INDEXED)?;
+                    }
+
+                    Ok(inserted)
+                },
+            )
+            .map_err(transaction_error)
+    }
+
+    async fn set_status(&self, message_id: B256, status: JobState) -> Result<(), Error> {
+        (&self.statuses, &self.by_status)
+            .transaction(|(statuses, by_status)| {
+                update_status(statuses, by_status, message_id, status)
+            })
+            .map_err(transaction_error)
+    }
+
+    async fn remove(&self, message_id: B256) -> Result<(), Error> {
+        (
+            &self.packets,
+            &self.statuses,
+            &self.by_nonce,
+            &self.by_src_eid,
+            &self.by_status,
+        )
+            .transaction(
+                |(packets, statuses, by_nonce, by_src_eid, by_status)| -> TransactionResult<()> {
+                    let Some(stored) = packets.remove(message_id.as_slice())? else {
+                        return Ok(());
+                    };
+                    let stored: StoredPacket = match serde_json::from_slice(&stored) {
+                        Ok(stored) => stored,
+                        Err(e) => return abort(store_error(e)),
+                    };
+
+                    if let Some(status) = statuses.remove(message_id.as_slice())? {
+                        if let Some(status) = status.first() {
+                            by_status.remove(index_key(&[*status], message_id))?;
+                        }
+                    }
+                    by_nonce.remove(index_key(&stored.packet.nonce.to_be_bytes(), message_id))?;
+                    by_src_eid
+                        .remove(index_key(&stored.packet.srcEid.to_be_bytes(), message_id))?;
+                    Ok(())
+                },
+            )
+            .map_err(transaction_error)
+    }
+
+    async fn message_ids(&self) -> Result<Vec<B256>, Error> {
+        self.packets
+            .iter()
+            .keys()
+            .map(|key| Ok(B256::from_slice(&key.map_err(store_error)?)))
+            .collect()
+    }
+
+    async fn find_by_nonce(&self, nonce: u64) -> Result<Vec<B256>, Error> {
+        scan(&self.by_nonce, &nonce.to_be_bytes())
+    }
+
+    async fn find_by_src_eid(&self, src_eid: u32) -> Result<Vec<B256>, Error> {
+        scan(&self.by_src_eid, &src_eid.to_be_bytes())
+    }
+
+    async fn find_by_status(&self, status: JobState) -> Result<Vec<B256>, Error> {
+        scan(&self.by_status, &[status as u8])
+    }
+
+    async fn get_job(&self, message_id: B256) -> Result<Option<JobRecord>, Error> {
+        self.job(message_id)
+    }
+
+    async fn put_job(&self, record: &JobRecord) -> Result<(), Error> {
+        let message_id = record.message_id;
+        let encoded = serde_json::to_vec(record).map_err(store_error)?;
+
+        (
+            &self.jobs,
+            &self.pending_jobs,
+            &self.statuses,
+            &self.by_status,
+        )
+            .transaction(|(jobs, pending_jobs, statuses, by_status)| {
+                jobs.insert(message_id.as_slice(), encoded.as_slice())?;
+                if record.state.is_in_progress() {
+                    pending_jobs.insert(message_id.as_slice(), INDEXED)?;
+                } else {
+                    pending_jobs.remove(message_id.as_slice())?;
+                }
+
+                update_status(statuses, by_status, message_id, record.state)
+            })
+            .map_err(transaction_error)
+    }
+
+    async fn remove_job(&self, message_id: B256) -> Result<(), Error> {
+        (&self.jobs, &self.pending_jobs)
+            .transaction(|(jobs, pending_jobs)| -> TransactionResult<()> {
+                pending_jobs.remove(message_id.as_slice())?;
+                jobs.remove(message_id.as_slice())?;
+                Ok(())
+            })
+            .map_err(transaction_error)
+    }
+
+    async fn jobs(&self) -> Result<Vec<JobRecord>, Error> {
+        self.jobs
+            .iter()
+            .values()
+            .map(|record| {
+                serde_json::from_slice(&record.map_err(store_error)?).map_err(store_error)
+            })
+            .collect()
+    }
+
+    async fn pending_jobs(&self) -> Result<Vec<JobRecord>, Error> {
+        let mut records = Vec::new();
+        for message_id in self.pending_jobs.iter().keys() {
+            let message_id = B256::from_slice(&message_id.map_err(store_error)?);
+            if let Some(record) = self.job(message_id)? {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+fn index_key(value: &[u8], message_id: B256) -> Vec<u8> {
+    let mut key = value.to_vec();
+    key.extend_from_slice(message_id.as_slice());
+    key
+}
+
+/// Message IDs indexed under `value` in `index`
+fn scan(index: &Tree, value: &[u8]) -> Result<Vec<B256>, Error> {
+    index
+        .scan_prefix(value)
+        .keys()
+        .map(|key| Ok(B256::from_slice(&key.map_err(store_error)?[value.len()..])))
+        .collect()
+}
@@ -0,0 +1,258 @@
+//! Packet store backed by an SQLite database
+
+use super::{store_error, PacketStore, StoredPacket};
+use crate::queue::{JobRecord, JobState};
+use alloy_primitives::B256;
+use async_trait::async_trait;
+use gadget_sdk::Error;
+use rusqlite::{params, Connection, OptionalExtension, Params};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS packets (
+        message_id BLOB PRIMARY KEY,
+        src_eid INTEGER NOT NULL,
+        nonce INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        packet TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS packets_src_eid ON packets (src_eid);
+    CREATE INDEX IF NOT EXISTS packets_nonce ON packets (nonce);
+    CREATE INDEX IF NOT EXISTS packets_status ON packets (status);
+    CREATE TABLE IF NOT EXISTS jobs (
+        message_id BLOB PRIMARY KEY,
+        in_progress INTEGER NOT NULL,
+        record TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS jobs_in_progress ON jobs (in_progress);
+";
+
+/// Packets kept in a single SQLite table, with indexed columns for every lookup, and jobs in
+/// another. Queries run on the blocking thread pool.
+#[derive(Debug)]
+pub struct SqlitePacketStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqlitePacketStore {
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        let conn = Connection::open(dir.join("packets.sqlite")).map_err(store_error)?;
+        conn.execute_batch(SCHEMA).map_err(store_error)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Run `f` with the connection on the blocking thread pool
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().map_err(store_error)?;
+            f(&mut conn).map_err(store_error)
+        })
+        .await
+        .map_err(store_error)?
+    }
+
+    async fn query_ids<P>(&self, sql: &'static str, params: P) -> Result<Vec<B256>, Error>
+    where
+        P: Params + Send + 'static,
+    {
+        self.with_conn(move |conn| {
+            let mut statement = conn.prepare_cached(sql)?;
+            let ids = statement
+                .query_map(params, |row| row.get::<_, Vec<u8>>(0))?
+                .map(|id| Ok(B256::from_slice(&id?)))
+                .collect();
+            ids
+        })
+        .await
+    }
+
+    async fn query_jobs(&self, sql: &'static str) -> Result<Vec<JobRecord>, Error> {
+        let records = self
+            .with_conn(move |conn| {
+                let mut statement = conn.prepare_cached(sql)?;
+                let records = statement
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>();
+                records
+            })
+            .await?;
+
+        records
+            .iter()
+            .map(|record| serde_json::from_str(record).map_err(store_error))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PacketStore for SqlitePacketStore {
+    async fn get(&self, message_id: B256) -> Result<Option<StoredPacket>, Error> {
+        let packet = self
+            .with_conn(move |conn| {
+                conn.query_row(
+                    "SELECT packet FROM packets WHERE message_id = ?1",
+                    [message_id.as_slice()],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+            })
+            .await?;
+
+        packet
+            .map(|packet| serde_json::from_str(&packet).map_err(store_error))
+            .transpose()
+    }
+
+    async fn insert(&self, message_id: B256, packet: &StoredPacket) -> Result<bool, Error> {
+        let encoded = serde_json::to_string(packet).map_err(store_error)?;
+        let src_eid = packet.packet.srcEid;
+        let nonce = packet.packet.nonce;
+        self.with_conn(move |conn| {
+            let updated = conn.execute(
+                "UPDATE packets SET packet = ?2 WHERE message_id = ?1",
+                params![message_id.as_slice(), encoded],
+            )?;
+            if updated > 0 {
+                return Ok(false);
+            }
+
+            conn.execute(
+                "INSERT INTO packets (message_id, src_eid, nonce, status, packet)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    message_id.as_slice(),
+                    src_eid,
+                    nonce as i64,
+                    status_name(JobState::Stored),
+                    encoded
+                ],
+            )?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn set_status(&self, message_id: B256, status: JobState) -> Result<(), Error> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE packets SET status = ?2 WHERE message_id = ?1",
+                params![message_id.as_slice(), status_name(status)],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove(&self, message_id: B256) -> Result<(), Error> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM packets WHERE message_id = ?1",
+                [message_id.as_slice()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn message_ids(&self) -> Result<Vec<B256>, Error> {
+        self.query_ids("SELECT message_id FROM packets ORDER BY rowid", ())
+            .await
+    }
+
+    async fn find_by_nonce(&self, nonce: u64) -> Result<Vec<B256>, Error> {
+        // Nonces are stored as their two's complement bit pattern in SQLite's signed integers
+        self.query_ids(
+            "SELECT message_id FROM packets WHERE nonce = ?1",
+            [nonce as i64],
+        )
+        .await
+    }
+
+    async fn find_by_src_eid(&self, src_eid: u32) -> Result<Vec<B256>, Error> {
+        self.query_ids(
+            "SELECT message_id FROM packets WHERE src_eid = ?1",
+            [src_eid],
+        )
+        .await
+    }
+
+    async fn find_by_status(&self, status: JobState) -> Result<Vec<B256>, Error> {
+        self.query_ids(
+            "SELECT message_id FROM packets WHERE status = ?1",
+            [status_name(status)],
+        )
+        .await
+    }
+
+    async fn get_job(&self, message_id: B256) -> Result<Option<JobRecord>, Error> {
+        let record = self
+            .with_conn(move |conn| {
+                conn.query_row(
+                    "SELECT record FROM jobs WHERE message_id = ?1",
+                    [message_id.as_slice()],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+            })
+            .await?;
+
+        record
+            .map(|record| serde_json::from_str(&record).map_err(store_error))
+            .transpose()
+    }
+
+    async fn put_job(&self, record: &JobRecord) -> Result<(), Error> {
+        let encoded = serde_json::to_string(record).map_err(store_error)?;
+        let message_id = record.message_id;
+        let state = record.state;
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO jobs (message_id, in_progress, record) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (message_id) DO UPDATE
+                 SET in_progress = excluded.in_progress, record = excluded.record",
+                params![message_id.as_slice(), state.is_in_progress(), encoded],
+            )?;
+            tx.execute(
+                "UPDATE packets SET status = ?2 WHERE message_id = ?1",
+                params![message_id.as_slice(), status_name(state)],
+            )?;
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn remove_job(&self, message_id: B256) -> Result<(), Error> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM jobs WHERE message_id = ?1",
+                [message_id.as_slice()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn jobs(&self) -> Result<Vec<JobRecord>, Error> {
+        self.query_jobs("SELECT record FROM jobs ORDER BY rowid")
+            .await
+    }
+
+    async fn pending_jobs(&self) -> Result<Vec<JobRecord>, Error> {
+        self.query_jobs("SELECT record FROM jobs WHERE in_progress = 1 ORDER BY rowid")
+            .await
+    }
+}
+
+fn status_name(status: JobState) -> String {
+    format!("{:?}", status)
+}
@@ -0,0 +1,243 @@
+//! Submission of `verifyMessageHash` transactions to the destination `LayerZeroDVNInstance`
+
+use crate::chains::Chain;
+use crate::LayerZeroDVNInstance::{self, LayerZeroDVNInstanceErrors};
+use alloy_network::EthereumWallet;
+use alloy_primitives::{Bytes, TxHash, B256};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_signer_local::PrivateKeySigner;
+use alloy_sol_types::SolInterface;
+use alloy_transport::{RpcError, TransportError};
+use gadget_sdk::Error;
+use std::fmt;
+use tokio::time::{sleep, Duration, Instant};
+
+/// How long to wait for a verification transaction to be mined
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Reasons a verification transaction can fail
+#[derive(Debug)]
+pub enum SubmitError {
+    /// The message was already verified by this DVN instance
+    MessageAlreadyVerified,
+    /// The packet's destination EID does not match the receive ULN's chain
+    DstEidMismatch,
+    /// The message hash could not be decoded by the DVN instance
+    InvalidMessageHash,
+    /// The transaction reverted for another reason
+    Reverted(String),
+    /// The transaction could not be sent or its receipt could not be fetched
+    Transport(String),
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MessageAlreadyVerified => write!(f, "Message already verified"),
+            Self::DstEidMismatch => write!(f, "Destination EID mismatch"),
+            Self::InvalidMessageHash => write!(f, "Invalid message hash"),
+            Self::Reverted(reason) => write!(f, "Verification reverted: {}", reason),
+            Self::Transport(reason) => write!(f, "Failed to submit verification: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+impl From<SubmitError> for Error {
+    fn from(e: SubmitError) -> Self {
+        Error::Client(e.to_string())
+    }
+}
+
+/// Sends `verifyMessageHash` transactions signed with the operator's ECDSA key
+pub struct VerificationSubmitter {
+    signer: PrivateKeySigner,
+}
+
+impl VerificationSubmitter {
+    pub fn new(signer: PrivateKeySigner) -> Self {
+        Self { signer }
+    }
+
+    /// Send a verification for `message_id` to our DVN instance on `destination`, returning the
+    /// hash of the pending transaction
+    pub async fn send(
+        &self,
+        destination: &Chain,
+        message_id: B256,
+        message: Bytes,
+    ) -> Result<TxHash, SubmitError> {
+        let url = destination
+            .config
+            .rpc_url
+            .parse()
+            .map_err(|e| SubmitError::Transport(format!("Invalid RPC URL: {}", e)))?;
+        let provider = ProviderBuilder::new()
+            .with_recommended_fillers()
+            .wallet(EthereumWallet::from(self.signer.clone()))
+            .on_http(url);
+
+        let dvn = LayerZeroDVNInstance::new(destination.config.dvn, provider);
+        let call = dvn.verifyMessageHash(message_id, message);
+
+        // Simulate first so reverts are reported with their decoded reason
+        call.call().await.map_err(decode_revert)?;
+
+        let pending = call.send().await.map_err(decode_revert)?;
+        Ok(*pending.tx_hash())
+    }
+
+    /// Wait for the transaction `tx_hash` sent by [`Self::send`] to be mined successfully. Failing
+    /// receipt requests are retried until [`RECEIPT_TIMEOUT`], so a flaky node is not mistaken for
+    /// a dropped transaction.
+    pub async fn wait_for_receipt(
+        &self,
+        destination: &Chain,
+        tx_hash: TxHash,
+    ) -> Result<(), SubmitError> {
+        let poll_interval =
+            Duration::from_secs(destination.config.confirmations.poll_interval_secs.max(1));
+        let deadline = Instant::now() + RECEIPT_TIMEOUT;
+
+        loop {
+            let receipt = match destination.provider.get_transaction_receipt(tx_hash).await {
+                Ok(receipt) => receipt,
+                Err(e) if Instant::now() < deadline => {
+                    tracing::debug!("Failed to get receipt for {}, retrying: {}", tx_hash, e);
+                    sleep(poll_interval).await;
+                    continue;
+                }
+                Err(e) => return Err(SubmitError::Transport(e.to_string())),
+            };
+
+            match receipt {
+                Some(receipt) if receipt.status() => return Ok(()),
+                Some(_) => {
+                    return Err(SubmitError::Reverted(format!(
+                        "transaction {} failed",
+                        tx_hash
+                    )))
+                }
+                None if Instant::now() >= deadline => {
+                    return Err(SubmitError::Transport(format!(
+                        "transaction {} was not mined",
+                        tx_hash
+                    )))
+                }
+                None => sleep(poll_interval).await,
+            }
+        }
+    }
+
+    /// Whether the transaction `tx_hash` may still land: it was mined without its receipt being
+    /// served yet, or it is known to the node and no other transaction of ours used its nonce
+    pub async fn is_pending(
+        &self,
+        destination: &Chain,
+        tx_hash: TxHash,
+    ) -> Result<bool, SubmitError> {
+        let transport = |e: TransportError| SubmitError::Transport(e.to_string());
+
+        let Some(tx) = destination
+            .provider
+            .get_transaction_by_hash(tx_hash)
+            .await
+            .map_err(transport)?
+        else {
+            return Ok(false);
+        };
+        if tx.block_number.is_some() {
+            return Ok(true);
+        }
+
+        let next_nonce = destination
+            .provider
+            .get_transaction_count(self.signer.address())
+            .await
+            .map_err(transport)?;
+        Ok(tx.nonce >= next_nonce)
+    }
+}
+
+/// Map a contract call error onto a [`SubmitError`], decoding `LayerZeroDVNInstance` custom errors
+fn decode_revert(err: alloy_contract::Error) -> SubmitError {
+    let alloy_contract::Error::TransportError(RpcError::ErrorResp(payload)) = &err else {
+        return SubmitError::Transport(err.to_string());
+    };
+
+    let revert_data = payload
+        .data
+        .as_ref()
+        .and_then(|data| serde_json::from_str::<Bytes>(data.get()).ok());
+
+    match revert_data.and_then(|data| LayerZeroDVNInstanceErrors::abi_decode(&data, true).ok()) {
+        Some(LayerZeroDVNInstanceErrors::MessageAlreadyVerified(_)) => {
+            SubmitError::MessageAlreadyVerified
+        }
+        Some(LayerZeroDVNInstanceErrors::DstEidMismatch(_)) => SubmitError::DstEidMismatch,
+        Some(LayerZeroDVNInstanceErrors::InvalidMessageHash(_)) => SubmitError::InvalidMessageHash,
+        Some(other) => SubmitError::Reverted(format!("{:?}", other)),
+        None => SubmitError::Reverted(payload.message.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::keccak256;
+
+    /// A JSON-RPC error response carrying the revert data of the custom error `signature`
+    fn revert(signature: &str) -> alloy_contract::Error {
+        let selector = Bytes::copy_from_slice(&keccak256(signature)[..4]);
+        revert_with_data(&format!("\"{}\"", selector))
+    }
+
+    fn revert_with_data(data: &str) -> alloy_contract::Error {
+        let payload = format!(
+            r#"{{ "code": 3, "message": "execution reverted", "data": {} }}"#,
+            data
+        );
+        alloy_contract::Error::TransportError(RpcError::ErrorResp(
+            serde_json::from_str(&payload).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_decodes_dvn_instance_errors() {
+        assert!(matches!(
+            decode_revert(revert("MessageAlreadyVerified()")),
+            SubmitError::MessageAlreadyVerified
+        ));
+        assert!(matches!(
+            decode_revert(revert("DstEidMismatch()")),
+            SubmitError::DstEidMismatch
+        ));
+        assert!(matches!(
+            decode_revert(revert("InvalidMessageHash()")),
+            SubmitError::InvalidMessageHash
+        ));
+        assert!(matches!(
+            decode_revert(revert("VerificationFailed()")),
+            SubmitError::Reverted(reason) if reason.contains("VerificationFailed")
+        ));
+    }
+
+    #[test]
+    fn test_unknown_reverts_and_transport_errors() {
+        // Reverts that are not DVN instance errors keep the node's message
+        assert!(matches!(
+            decode_revert(revert("Unauthorized()")),
+            SubmitError::Reverted(reason) if reason == "execution reverted"
+        ));
+        assert!(matches!(
+            decode_revert(revert_with_data("null")),
+            SubmitError::Reverted(reason) if reason == "execution reverted"
+        ));
+
+        assert!(matches!(
+            decode_revert(alloy_contract::Error::TransportError(RpcError::NullResp)),
+            SubmitError::Transport(_)
+        ));
+    }
+}